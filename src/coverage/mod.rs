@@ -0,0 +1,179 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::monkey::interpreter::hook::Hook;
+use crate::monkey::interpreter::{Environment, Evaluate};
+use crate::monkey::parser::ast::visit::{walk_expression, walk_statement};
+use crate::monkey::parser::ast::{ExpressionNode, Program, StatementNode, Visitor};
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+
+/// An `if` along with how often each of its branches ran.
+#[derive(Debug, Clone, PartialEq)]
+struct Branch {
+    /// Line of the statement the `if` belongs to.
+    line: usize,
+    /// Times the consequence and the alternative were picked.
+    taken: [u64; 2],
+}
+
+/// Hook recording which statements and `if` branches of a program ran.
+pub struct Coverage {
+    /// Execution count of every line holding a statement.
+    lines: RefCell<BTreeMap<usize, u64>>,
+    /// Keyed by the byte offset of the statement and the index of the `if` within it.
+    branches: RefCell<BTreeMap<(usize, usize), Branch>>,
+}
+
+impl Coverage {
+    /// Coverage of `program`, with every statement and `if` it holds not run yet.
+    pub fn new(program: &Program) -> Self {
+        let mut sites = Sites::default();
+        sites.visit_program(program);
+
+        Coverage { lines: RefCell::new(sites.lines), branches: RefCell::new(sites.branches) }
+    }
+
+    /// The coverage of `path` in the lcov tracefile format.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", path);
+
+        let branches = self.branches.borrow();
+        for (block, branch) in branches.values().enumerate() {
+            // The branches of an `if` that never ran are reported as `-` rather than 0.
+            let reached = branch.taken.iter().any(|&taken| taken > 0);
+            for (idx, taken) in branch.taken.iter().enumerate() {
+                let taken = if reached { taken.to_string() } else { "-".to_string() };
+                lcov += &format!("BRDA:{},{},{},{}\n", branch.line, block, idx, taken);
+            }
+        }
+        let (found, hit) = self.branch_totals();
+        lcov += &format!("BRF:{}\nBRH:{}\n", found, hit);
+
+        let lines = self.lines.borrow();
+        for (line, count) in lines.iter() {
+            lcov += &format!("DA:{},{}\n", line, count);
+        }
+        let (found, hit) = self.line_totals();
+        lcov += &format!("LF:{}\nLH:{}\nend_of_record\n", found, hit);
+
+        lcov
+    }
+
+    /// One line summary of the coverage of `path`.
+    pub fn summary(&self, path: &str) -> String {
+        let (lines, lines_hit) = self.line_totals();
+        let (branches, branches_hit) = self.branch_totals();
+
+        format!(
+            "{}: lines {}/{} ({}), branches {}/{} ({})",
+            path, lines_hit, lines, percent(lines_hit, lines), branches_hit, branches, percent(branches_hit, branches),
+        )
+    }
+
+    fn line_totals(&self) -> (usize, usize) {
+        let lines = self.lines.borrow();
+        (lines.len(), lines.values().filter(|&&count| count > 0).count())
+    }
+
+    fn branch_totals(&self) -> (usize, usize) {
+        let branches = self.branches.borrow();
+        let hit = branches.values().flat_map(|branch| branch.taken).filter(|&taken| taken > 0).count();
+        (branches.len() * 2, hit)
+    }
+}
+
+fn percent(hit: usize, found: usize) -> String {
+    match found {
+        0 => "-".to_string(),
+        found => format!("{:.1}%", hit as f64 * 100.0 / found as f64),
+    }
+}
+
+impl Hook for Coverage {
+    fn before_statement(&self, _eval: &Evaluate, _statement: &StatementNode, span: &Span, _env: &Rc<RefCell<Environment>>) -> Result<()> {
+        *self.lines.borrow_mut().entry(span.line_start + 1).or_default() += 1;
+        Ok(())
+    }
+
+    fn branch(&self, statement: &Span, index: usize, taken: bool) {
+        let mut branches = self.branches.borrow_mut();
+        let branch = branches.entry((statement.byte_start, index))
+            .or_insert_with(|| Branch { line: statement.line_start + 1, taken: [0, 0] });
+
+        branch.taken[if taken { 0 } else { 1 }] += 1;
+    }
+}
+
+/// Statements and `if`s of a program, numbering the `if`s the way `Hook::branch` does.
+#[derive(Default)]
+struct Sites {
+    lines: BTreeMap<usize, u64>,
+    branches: BTreeMap<(usize, usize), Branch>,
+    statement: Option<Span>,
+    /// Number of `if`s found so far in the statement.
+    index: usize,
+}
+
+impl Sites {
+    fn branch(&mut self) {
+        if let Some(statement) = &self.statement {
+            let branch = Branch { line: statement.line_start + 1, taken: [0, 0] };
+            self.branches.insert((statement.byte_start, self.index), branch);
+            self.index += 1;
+        }
+    }
+}
+
+impl Visitor for Sites {
+    fn visit_program(&mut self, program: &Program) {
+        for (idx, statement) in program.statements.iter().enumerate() {
+            let span = program.span(idx).cloned();
+            if let Some(span) = &span {
+                self.lines.insert(span.line_start + 1, 0);
+            }
+
+            let outer = (std::mem::replace(&mut self.statement, span), std::mem::replace(&mut self.index, 0));
+            self.visit_statement(statement);
+            (self.statement, self.index) = outer;
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        if let StatementNode::If { .. } = statement {
+            self.branch();
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionNode) {
+        if let ExpressionNode::If { .. } = expression {
+            self.branch();
+        }
+        walk_expression(self, expression)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let contents = fs::read_to_string("monkey/test_coverage.mky").unwrap();
+        let lexer = MonkeyLexer::new(&contents);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let coverage = Rc::new(Coverage::new(&program));
+        Evaluate::new().with_hook(coverage.clone()).evaluate(&program).unwrap();
+
+        let output = format!("{}\n\n{}", coverage.summary("monkey/test_coverage.mky"), coverage.lcov("monkey/test_coverage.mky"));
+        insta::assert_snapshot!(output);
+    }
+}
@@ -0,0 +1,284 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use crate::debugger::{variables, Mode, Stepper, Stop};
+use crate::monkey::error::Error;
+use crate::monkey::interpreter::hook::Hook;
+use crate::monkey::interpreter::{Environment, Evaluate};
+use crate::monkey::lexer::MonkeyLexer;
+use crate::monkey::parser::ast::StatementNode;
+use crate::monkey::parser::Parser;
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+use crate::rpc::{read_message, write_message};
+
+/// Monkey runs on a single thread, reported to the client under this id.
+const THREAD_ID: i64 = 1;
+
+/// `variablesReference` of the locals of the innermost frame, the only scope with variables.
+const LOCALS: i64 = 1;
+
+/// What the server does after handling a request.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Wait,
+    /// Start the launched program.
+    Run,
+    /// Let the paused program go on.
+    Resume,
+    Disconnect,
+}
+
+/// State of a paused program that requests can look at.
+struct Paused<'a> {
+    eval: &'a Evaluate,
+    env: &'a Rc<RefCell<Environment>>,
+    line: usize,
+    depth: usize,
+}
+
+/// Debug Adapter Protocol server, reading requests from `input` and writing responses and
+/// events to `output`.
+///
+/// Requests are read while the program is paused, from within the evaluator hook, so the
+/// program and the server share one thread.
+pub struct Server<R, W> {
+    input: RefCell<R>,
+    output: RefCell<W>,
+    seq: Cell<i64>,
+    stepper: Stepper,
+    /// Path and contents of the launched program.
+    program: RefCell<Option<(String, String)>>,
+    stop_on_entry: Cell<bool>,
+    started: Cell<bool>,
+    disconnected: Cell<bool>,
+}
+
+/// Serve a single debug session, returning once the client disconnects.
+pub fn serve<R: BufRead + 'static, W: Write + 'static>(input: R, output: W) {
+    let server = Rc::new(Server {
+        input: RefCell::new(input),
+        output: RefCell::new(output),
+        seq: Cell::new(1),
+        stepper: Stepper::new(&[], Mode::Continue),
+        program: RefCell::new(None),
+        stop_on_entry: Cell::new(false),
+        started: Cell::new(false),
+        disconnected: Cell::new(false),
+    });
+
+    while !server.disconnected.get() {
+        if server.wait(None) == Action::Run {
+            server.run(Rc::clone(&server));
+        }
+    }
+}
+
+impl<R: BufRead + 'static, W: Write + 'static> Server<R, W> {
+    fn run(&self, hook: Rc<Self>) {
+        let Some((_, contents)) = self.program.borrow().clone() else {
+            return;
+        };
+        self.started.set(true);
+
+        let lexer = MonkeyLexer::new(&contents);
+        let result = Parser::new(&lexer).parse_program()
+            .and_then(|program| Evaluate::new().with_hook(hook).evaluate(&program));
+
+        let exit_code = match result {
+            Ok(out) => {
+                self.event("output", json!({ "category": "stdout", "output": format!("{}\n", out) }));
+                0
+            }
+            Err(Error::AbortedError) => return,
+            Err(Error::ExitError(code)) => code,
+            Err(err) => {
+                self.event("output", json!({ "category": "stderr", "output": format!("{}\n", err) }));
+                1
+            }
+        };
+
+        self.event("exited", json!({ "exitCode": exit_code }));
+        self.event("terminated", json!({}));
+    }
+
+    /// Handle requests until one starts, resumes or ends the program.
+    fn wait(&self, paused: Option<&Paused>) -> Action {
+        loop {
+            let Some(request) = self.read() else {
+                self.disconnected.set(true);
+                return Action::Disconnect;
+            };
+
+            match self.handle(&request, paused) {
+                Action::Wait => {}
+                action => return action,
+            }
+        }
+    }
+
+    fn handle(&self, request: &Value, paused: Option<&Paused>) -> Action {
+        let args = &request["arguments"];
+
+        match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                self.respond(request, json!({ "supportsConfigurationDoneRequest": true }));
+                self.event("initialized", json!({}));
+            }
+            "launch" => {
+                let path = args["program"].as_str().unwrap_or("");
+                match fs::read_to_string(path) {
+                    Ok(contents) => {
+                        *self.program.borrow_mut() = Some((path.to_string(), contents));
+                        self.stop_on_entry.set(args["stopOnEntry"].as_bool().unwrap_or(false));
+                        self.respond(request, json!({}));
+                    }
+                    Err(err) => self.fail(request, &format!("{}: {}", path, err)),
+                }
+            }
+            "setBreakpoints" => {
+                let lines: Vec<usize> = args["breakpoints"].as_array().into_iter().flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect();
+
+                *self.stepper.breakpoints.borrow_mut() = lines.iter().copied().collect();
+                let breakpoints: Vec<Value> = lines.iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.respond(request, json!({ "breakpoints": breakpoints }));
+            }
+            "configurationDone" => {
+                self.respond(request, json!({}));
+                if !self.started.get() && self.program.borrow().is_some() {
+                    self.stepper.resume(if self.stop_on_entry.get() { Mode::Step } else { Mode::Continue });
+                    return Action::Run;
+                }
+            }
+            "threads" => self.respond(request, json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => {
+                let frames = paused.map(|paused| self.stack_frames(paused)).unwrap_or_default();
+                self.respond(request, json!({ "stackFrames": frames, "totalFrames": frames.len() }));
+            }
+            "scopes" => {
+                let scopes = match args["frameId"].as_i64() {
+                    Some(0) => json!([{ "name": "Locals", "variablesReference": LOCALS, "expensive": false }]),
+                    _ => json!([]),
+                };
+                self.respond(request, json!({ "scopes": scopes }));
+            }
+            "variables" => {
+                let variables: Vec<Value> = match (paused, args["variablesReference"].as_i64()) {
+                    (Some(paused), Some(LOCALS)) => variables(&paused.env.borrow()).into_iter()
+                        .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+                        .collect(),
+                    _ => vec![],
+                };
+                self.respond(request, json!({ "variables": variables }));
+            }
+            "continue" | "next" | "stepIn" | "stepOut" if paused.is_some() => {
+                let depth = paused.map_or(0, |paused| paused.depth);
+                self.stepper.resume(match request["command"].as_str() {
+                    Some("next") => Mode::Next(depth),
+                    Some("stepIn") => Mode::Step,
+                    Some("stepOut") => Mode::Out(depth),
+                    _ => Mode::Continue,
+                });
+                self.respond(request, json!({ "allThreadsContinued": true }));
+                return Action::Resume;
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, json!({}));
+                self.disconnected.set(true);
+                return Action::Disconnect;
+            }
+            command => self.fail(request, &format!("Unsupported request {:?}", command)),
+        }
+
+        Action::Wait
+    }
+
+    /// Frames of the paused program, innermost first.
+    ///
+    /// Each frame is shown at the line it is paused on, which for callers is where they made
+    /// the call.
+    fn stack_frames(&self, paused: &Paused) -> Vec<Value> {
+        let path = self.program.borrow().as_ref().map(|(path, _)| path.clone()).unwrap_or_default();
+        let calls = paused.eval.call_stack();
+
+        let names = calls.iter().map(|frame| frame.name.clone()).chain(["<main>".to_string()]);
+        let lines = [Some(paused.line)].into_iter().chain(calls.iter().map(|frame| frame.line));
+
+        names.zip(lines).enumerate()
+            .map(|(id, (name, line))| json!({
+                "id": id,
+                "name": name,
+                "line": line.unwrap_or(0),
+                "column": 1,
+                "source": { "path": path },
+            }))
+            .collect()
+    }
+
+    /// Read the next message, `None` once the client is gone.
+    fn read(&self) -> Option<Value> {
+        read_message(&mut *self.input.borrow_mut())
+    }
+
+    fn send(&self, mut message: Value) {
+        message["seq"] = json!(self.seq.replace(self.seq.get() + 1));
+
+        write_message(&mut *self.output.borrow_mut(), &message);
+    }
+
+    fn respond(&self, request: &Value, body: Value) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, request: &Value, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
+
+impl<R: BufRead + 'static, W: Write + 'static> Hook for Server<R, W> {
+    fn before_statement(&self, eval: &Evaluate, _statement: &StatementNode, span: &Span, env: &Rc<RefCell<Environment>>) -> Result<()> {
+        let line = span.line_start + 1;
+        let depth = eval.call_stack().len();
+
+        let Some(stop) = self.stepper.check(line, depth) else {
+            return Ok(());
+        };
+
+        let reason = match stop {
+            Stop::Step if self.stop_on_entry.replace(false) => "entry",
+            Stop::Step => "step",
+            Stop::Breakpoint => "breakpoint",
+        };
+        self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+
+        match self.wait(Some(&Paused { eval, env, line, depth })) {
+            Action::Disconnect => Err(Error::AbortedError),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::monkey::error::Error;
+use crate::monkey::interpreter::hook::Hook;
+use crate::monkey::interpreter::object::Object;
+use crate::monkey::interpreter::{Environment, Evaluate};
+use crate::monkey::lexer::MonkeyLexer;
+use crate::monkey::parser::ast::StatementNode;
+use crate::monkey::parser::Parser;
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+
+const HELP: &str = "\
+s, step          run to the next statement, entering calls
+n, next          run to the next statement in this call
+o, out           run until this call returns
+c, continue      run to the next breakpoint
+b, break LINE    pause whenever LINE is reached
+d, delete LINE   remove the breakpoint on LINE
+v, vars          print the bindings visible here
+p, print EXPR    evaluate EXPR here and print the result
+bt, backtrace    print the active calls
+q, quit          stop the program";
+
+/// When to pause next.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Step,
+    /// Pause once the call depth is at most this.
+    Next(usize),
+    /// Pause once the call depth is below this.
+    Out(usize),
+    Continue,
+}
+
+/// Why the program paused.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint,
+}
+
+/// Decides which statements a debugged program pauses at.
+pub struct Stepper {
+    pub breakpoints: RefCell<BTreeSet<usize>>,
+    mode: Cell<Mode>,
+    /// Line of the last statement run, a breakpoint only pauses when its line is entered.
+    last_line: Cell<usize>,
+}
+
+impl Stepper {
+    pub fn new(breakpoints: &[usize], mode: Mode) -> Self {
+        Stepper {
+            breakpoints: RefCell::new(breakpoints.iter().copied().collect()),
+            mode: Cell::new(mode),
+            last_line: Cell::new(0),
+        }
+    }
+
+    pub fn resume(&self, mode: Mode) {
+        self.mode.set(mode);
+    }
+
+    /// Whether to pause before a statement on `line` run `depth` calls deep.
+    pub fn check(&self, line: usize, depth: usize) -> Option<Stop> {
+        let entered = self.last_line.replace(line) != line;
+
+        let step = match self.mode.get() {
+            Mode::Step => true,
+            Mode::Next(max) => depth <= max,
+            Mode::Out(below) => depth < below,
+            Mode::Continue => false,
+        };
+
+        if step {
+            Some(Stop::Step)
+        } else if entered && self.breakpoints.borrow().contains(&line) {
+            Some(Stop::Breakpoint)
+        } else {
+            None
+        }
+    }
+}
+
+/// Line based debugger, pausing before statements to take commands from `input`.
+pub struct Debugger<R, W> {
+    lines: Vec<String>,
+    input: RefCell<R>,
+    output: RefCell<W>,
+    stepper: Stepper,
+    /// Set while a `print` runs so its statements don't pause.
+    evaluating: Cell<bool>,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// Debug `source`, pausing at the first statement unless `breakpoints` are given.
+    pub fn new(source: &str, breakpoints: &[usize], input: R, output: W) -> Self {
+        Debugger {
+            lines: source.lines().map(str::to_string).collect(),
+            input: RefCell::new(input),
+            output: RefCell::new(output),
+            stepper: Stepper::new(breakpoints, if breakpoints.is_empty() { Mode::Step } else { Mode::Continue }),
+            evaluating: Cell::new(false),
+        }
+    }
+
+    fn say(&self, text: impl Display) {
+        let _ = writeln!(self.output.borrow_mut(), "{}", text);
+    }
+
+    fn show(&self, line: usize) {
+        let text = self.lines.get(line - 1).map(String::as_str).unwrap_or("");
+        self.say(format!("-> {} | {}", line, text));
+    }
+
+    /// Take commands until one resumes the program.
+    fn prompt(&self, eval: &Evaluate, env: &Rc<RefCell<Environment>>, line: usize, depth: usize) -> Result<()> {
+        loop {
+            let mut output = self.output.borrow_mut();
+            let _ = write!(output, "(debug) ");
+            let _ = output.flush();
+            drop(output);
+
+            let mut command = String::new();
+            match self.input.borrow_mut().read_line(&mut command) {
+                Ok(0) | Err(_) => return Err(Error::AbortedError),
+                Ok(_) => {}
+            }
+
+            let command = command.trim();
+            let (name, arg) = command.split_once(' ').map_or((command, ""), |(name, arg)| (name, arg.trim()));
+            match name {
+                "s" | "step" => return self.resume(Mode::Step),
+                "n" | "next" => return self.resume(Mode::Next(depth)),
+                "o" | "out" => return self.resume(Mode::Out(depth)),
+                "c" | "continue" => return self.resume(Mode::Continue),
+                "b" | "break" | "d" | "delete" => match arg.parse::<usize>() {
+                    Ok(target) if name.starts_with('b') => {
+                        self.stepper.breakpoints.borrow_mut().insert(target);
+                        self.say(format!("Breakpoint at line {}", target));
+                    }
+                    Ok(target) => match self.stepper.breakpoints.borrow_mut().remove(&target) {
+                        true => self.say(format!("Deleted breakpoint at line {}", target)),
+                        false => self.say(format!("No breakpoint at line {}", target)),
+                    },
+                    Err(_) => self.say(format!("Expected a line number, got {:?}", arg)),
+                },
+                "v" | "vars" => self.vars(env),
+                "p" | "print" => self.print(eval, env, arg),
+                "bt" | "backtrace" => {
+                    self.say(format!("at line {}", line));
+                    for frame in eval.call_stack() {
+                        self.say(frame);
+                    }
+                }
+                "q" | "quit" => return Err(Error::AbortedError),
+                "h" | "help" => self.say(HELP),
+                "" => {}
+                _ => self.say(format!("Unknown command {:?}, try help", name)),
+            }
+        }
+    }
+
+    fn resume(&self, mode: Mode) -> Result<()> {
+        self.stepper.resume(mode);
+        Ok(())
+    }
+
+    fn vars(&self, env: &Rc<RefCell<Environment>>) {
+        for (name, val) in variables(&env.borrow()) {
+            self.say(format!("{} = {}", name, val));
+        }
+    }
+
+    fn print(&self, eval: &Evaluate, env: &Rc<RefCell<Environment>>, source: &str) {
+        let lexer = MonkeyLexer::new(source);
+        let program = match Parser::new(&lexer).parse_program() {
+            Ok(program) => program,
+            Err(err) => return self.say(err),
+        };
+
+        self.evaluating.set(true);
+        let result = eval.evaluate_program(&program, env);
+        self.evaluating.set(false);
+
+        match result {
+            Ok(val) => self.say(describe(&val)),
+            Err(err) => self.say(err),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_statement(&self, eval: &Evaluate, _statement: &StatementNode, span: &Span, env: &Rc<RefCell<Environment>>) -> Result<()> {
+        if self.evaluating.get() {
+            return Ok(());
+        }
+
+        let line = span.line_start + 1;
+        let depth = eval.call_stack().len();
+        if self.stepper.check(line, depth).is_none() {
+            return Ok(());
+        }
+
+        self.show(line);
+        self.prompt(eval, env, line, depth)
+    }
+}
+
+/// Bindings of `env` other than the builtins, sorted by name and described.
+pub fn variables(env: &Environment) -> Vec<(String, String)> {
+    let mut bindings: Vec<_> = env.bindings()
+        .filter(|(_, val)| !matches!(val, Object::BuiltIn { .. }))
+        .map(|(name, val)| (name.clone(), describe(val)))
+        .collect();
+    bindings.sort();
+    bindings
+}
+
+/// Short form of `val`, functions are shown by their parameters only.
+pub fn describe(val: &Object) -> String {
+    match val {
+        Object::Function { ident, .. } => {
+            let params: Vec<&str> = ident.iter().map(|ident| ident.0.as_str()).collect();
+            format!("fn({})", params.join(", "))
+        }
+        Object::BuiltIn { name, .. } => format!("builtin {}", name),
+        val => val.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn session(breakpoints: &[usize], commands: &'static str) -> String {
+        let source = fs::read_to_string("monkey/test_debugger.mky").unwrap();
+        let lexer = MonkeyLexer::new(&source);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let debugger = Rc::new(Debugger::new(&source, breakpoints, commands.as_bytes(), Vec::new()));
+        let eval = Evaluate::new().with_hook(debugger.clone());
+        let result = eval.evaluate(&program);
+        drop(eval);
+
+        let debugger = Rc::try_unwrap(debugger).ok().unwrap();
+        let transcript = String::from_utf8(debugger.output.into_inner()).unwrap();
+        format!("{}\n=> {:?}", transcript.replace("(debug) ", "(debug)\n"), result)
+    }
+
+    #[test]
+    fn test_debugger_step() {
+        insta::assert_snapshot!(session(&[], "s\ns\ns\nv\nbt\np n * 10\np missing(\ns\ns\nn\nbt\no\nbt\nn\nc\n"));
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() {
+        insta::assert_snapshot!(session(&[3], "bt\nv\nd 3\nb 10\nc\np total\nc\n"));
+    }
+
+    #[test]
+    fn test_debugger_quit() {
+        insta::assert_snapshot!(session(&[], "help\nq\n"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+
+use serde_json::{json, Value};
+
+use crate::monkey::checker::{analyze, Analysis, BindingKind, Definition, Severity};
+use crate::monkey::error::Error;
+use crate::monkey::formatter::format_source;
+use crate::monkey::interpreter::Environment;
+use crate::monkey::lexer::MonkeyLexer;
+use crate::monkey::parser::Parser;
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+use crate::rpc::{read_message, write_message};
+
+const KEYWORDS: [&str; 7] = ["let", "fn", "if", "else", "return", "true", "false"];
+
+/// JSON-RPC error code for requests the server does not know.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for requests the server could not carry out.
+const REQUEST_FAILED: i64 = -32803;
+
+/// Open document along with what the checker found in it.
+struct Document {
+    text: String,
+    /// The error instead when the text does not parse.
+    analysis: Result<Analysis>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let lexer = MonkeyLexer::new(&text);
+        let analysis = Parser::new(&lexer).parse_program().map(|program| analyze(&program));
+
+        Document { text, analysis }
+    }
+
+    /// Parser error or checker diagnostics.
+    fn diagnostics(&self) -> Vec<Value> {
+        let analysis = match &self.analysis {
+            Ok(analysis) => analysis,
+            Err(err) => return vec![json!({ "range": self.range(&error_span(err)), "severity": 1, "source": "monkey", "message": err.to_string() })],
+        };
+
+        analysis.diagnostics.iter()
+            .map(|diagnostic| json!({
+                "range": self.range(&diagnostic.span),
+                "severity": if diagnostic.severity == Severity::Error { 1 } else { 2 },
+                "source": "monkey",
+                "message": diagnostic.message,
+            }))
+            .collect()
+    }
+
+    /// Definition of the name at `position`, whether it is used or bound there.
+    fn definition_at(&self, position: &Value) -> Option<&Definition> {
+        let analysis = self.analysis.as_ref().ok()?;
+
+        let reference = analysis.references.iter()
+            .find(|reference| self.contains(&reference.span, position))
+            .and_then(|reference| reference.definition);
+        let definition = reference.or_else(|| {
+            analysis.definitions.iter().position(|definition| definition.kind != BindingKind::BuiltIn && self.contains(&definition.span, position))
+        })?;
+
+        analysis.definitions.get(definition)
+    }
+
+    /// LSP range of `span`, in UTF-16 code units as the protocol counts columns.
+    fn range(&self, span: &Span) -> Value {
+        json!({
+            "start": { "line": span.line_start, "character": self.column(span.byte_start) },
+            "end": { "line": span.line_end, "character": self.column(span.byte_end) },
+        })
+    }
+
+    fn contains(&self, span: &Span, position: &Value) -> bool {
+        let offset = self.offset(position);
+        span.byte_start <= offset && offset <= span.byte_end
+    }
+
+    /// UTF-16 column of the byte `offset`.
+    fn column(&self, offset: usize) -> usize {
+        let offset = offset.min(self.text.len());
+        let line_start = self.text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        self.text[line_start..offset].encode_utf16().count()
+    }
+
+    /// Byte offset of the LSP `position`, clamped to the end of its line.
+    fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+        let line_start: usize = self.text.split_inclusive('\n').take(line).map(str::len).sum();
+        let mut units = 0;
+        for (index, c) in self.text[line_start..].char_indices() {
+            if units >= character || c == '\n' {
+                return line_start + index;
+            }
+            units += c.len_utf16();
+        }
+
+        self.text.len()
+    }
+}
+
+/// Language Server Protocol server over `input` and `output`, returning after `exit`.
+pub struct Server<W> {
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) {
+    let mut server = Server { output, documents: HashMap::new() };
+
+    while let Some(message) = read_message(&mut input) {
+        if message["method"] == "exit" {
+            return;
+        }
+
+        let params = &message["params"];
+        let method = message["method"].as_str().unwrap_or("");
+        let result = server.handle(method, params);
+
+        // Notifications carry no id and get no response, unknown ones are ignored.
+        if let Some(id) = message.get("id") {
+            let response = match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+            };
+            write_message(&mut server.output, &response);
+        }
+    }
+}
+
+impl<W: Write> Server<W> {
+    /// Result of the request, or the JSON-RPC error code and message it failed with.
+    fn handle(&mut self, method: &str, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+
+        Ok(match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": { "name": "monkey" },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.open(uri, text.to_string());
+                Value::Null
+            }
+            "textDocument/didChange" => {
+                // The server asks for full syncs, so the last change holds the whole text.
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|changes| changes.last()).and_then(|change| change["text"].as_str()) {
+                    self.open(uri, text.to_string());
+                }
+                Value::Null
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }));
+                Value::Null
+            }
+            "textDocument/hover" => match self.documents.get(&uri).and_then(|document| document.definition_at(&params["position"])) {
+                Some(definition) => json!({
+                    "contents": { "kind": "markdown", "value": format!("```monkey\n{}\n```", describe(definition)) },
+                }),
+                None => Value::Null,
+            },
+            "textDocument/definition" => {
+                let document = self.documents.get(&uri);
+                match document.and_then(|document| Some((document, document.definition_at(&params["position"])?))) {
+                    Some((document, definition)) if definition.kind != BindingKind::BuiltIn => json!({ "uri": uri, "range": document.range(&definition.span) }),
+                    _ => Value::Null,
+                }
+            }
+            "textDocument/completion" => json!(self.completions(&uri)),
+            "textDocument/documentSymbol" => json!(self.symbols(&uri)),
+            "textDocument/formatting" => {
+                let Some(document) = self.documents.get(&uri) else {
+                    return Ok(Value::Null);
+                };
+                let formatted = format_source(&document.text).map_err(|err| (REQUEST_FAILED, err.to_string()))?;
+
+                let lines = document.text.lines().count();
+                json!([{ "range": { "start": { "line": 0, "character": 0 }, "end": { "line": lines + 1, "character": 0 } }, "newText": formatted }])
+            }
+            method => return Err((METHOD_NOT_FOUND, format!("Unsupported method {:?}", method))),
+        })
+    }
+
+    fn open(&mut self, uri: String, text: String) {
+        let document = Document::new(text);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.clone(), document);
+        self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }));
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        write_message(&mut self.output, &json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Keywords, builtins and every name the document binds.
+    fn completions(&self, uri: &str) -> Vec<Value> {
+        let mut items: BTreeSet<(String, i64)> = KEYWORDS.iter().map(|keyword| (keyword.to_string(), 14)).collect();
+        for (name, _) in Environment::new().bindings() {
+            items.insert((name.clone(), 3));
+        }
+
+        // Documents which do not parse, as while typing, still complete keywords and builtins.
+        if let Some(analysis) = self.documents.get(uri).and_then(|document| document.analysis.as_ref().ok()) {
+            for definition in analysis.definitions.iter().filter(|definition| definition.kind != BindingKind::BuiltIn) {
+                let kind = if definition.arity.is_some() || definition.params.is_some() { 3 } else { 6 };
+                items.insert((definition.name.clone(), kind));
+            }
+        }
+
+        items.into_iter()
+            .map(|(label, kind)| json!({ "label": label, "kind": kind }))
+            .collect()
+    }
+
+    /// The `let` bindings of the document, in source order.
+    fn symbols(&self, uri: &str) -> Vec<Value> {
+        let Some(document) = self.documents.get(uri) else {
+            return vec![];
+        };
+        let Ok(analysis) = &document.analysis else {
+            return vec![];
+        };
+
+        let mut lets: Vec<&Definition> = analysis.definitions.iter()
+            .filter(|definition| definition.kind == BindingKind::Let)
+            .collect();
+        lets.sort_by_key(|definition| definition.span.byte_start);
+
+        lets.into_iter()
+            .map(|definition| json!({
+                "name": definition.name,
+                "detail": describe(definition),
+                "kind": if definition.params.is_some() { 12 } else { 13 },
+                "range": document.range(&definition.span),
+                "selectionRange": document.range(&definition.span),
+            }))
+            .collect()
+    }
+}
+
+/// Where `err` happened, the start of the file for errors without a position.
+fn error_span(err: &Error) -> Span {
+    match err {
+        Error::InvalidTokenError(token) => token.span.clone(),
+        Error::UnterminatedStringError(span) => span.clone(),
+        _ => Span::default(),
+    }
+}
+
+/// `let name = fn(a, b)`, `a` for parameters or `len(_)` for builtins, `args` for builtin values.
+fn describe(definition: &Definition) -> String {
+    match (definition.kind, &definition.params) {
+        (BindingKind::Let, Some(params)) => format!("let {} = fn({})", definition.name, params.join(", ")),
+        (BindingKind::Let, None) => format!("let {}", definition.name),
+        (BindingKind::Param, _) => format!("parameter {}", definition.name),
+        (BindingKind::BuiltIn, Some(params)) => format!("builtin {}({})", definition.name, params.join(", ")),
+        (BindingKind::BuiltIn, None) => format!("builtin {}", definition.name),
+    }
+}
+
@@ -1,37 +1,544 @@
 #![allow(unused)]
 
+use std::cell::RefCell;
 use std::fs;
-use clap::Parser;
-use crate::monkey::interpreter::Evaluate;
-use crate::monkey::lexer::MonkeyLexer;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::process;
+use std::time::{Duration, Instant};
+use clap::{Parser, Subcommand};
+use crate::coverage::Coverage;
+use crate::debugger::Debugger;
+use crate::monkey::checker::{check_source, Severity};
+use crate::monkey::error::Error;
+use crate::monkey::formatter::format_source;
+use crate::monkey::interpreter::{Environment, Evaluate, DEFAULT_MAX_DEPTH};
+use crate::monkey::interpreter::capability::Capabilities;
+use crate::monkey::interpreter::object::{NULL, Object};
+use crate::monkey::lexer::{eval_lexer, MonkeyLexer};
+use crate::monkey::optimizer::optimize;
+use crate::monkey::parser::ast::{Program, StatementNode};
+use crate::profiler::Profiler;
 use crate::repl::Repl;
+mod dap;
+mod coverage;
+mod debugger;
+mod lsp;
+mod profiler;
 mod monkey;
 mod repl;
+mod rpc;
 
 #[derive(Parser, Debug)]
-
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(short, long, default_value = "")]
-    input: String,
+    /// Evaluate the -e source once for every line of stdin, bound to line and its number to nr,
+    /// printing the values which are not null unless the source ends with a let
+    #[arg(short = 'n', requires = "expression")]
+    lines: bool,
+
+    #[arg(short, long = "expr", value_name = "SOURCE", requires = "lines")]
+    expression: Option<String>,
+
+    /// Source to evaluate before the first line, in the environment the lines share
+    #[arg(long, value_name = "SOURCE", requires = "lines")]
+    begin: Option<String>,
+
+    /// Source to evaluate after the last line, printing its value unless null
+    #[arg(long, value_name = "SOURCE", requires = "lines")]
+    end: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
-    let args = Args::parse();
+/// How `run` and `eval` evaluate the program.
+#[derive(clap::Args, Debug)]
+struct RunOptions {
+    /// Fold constants and drop dead code before running the input
+    #[arg(long)]
+    optimize: bool,
+
+    /// Fail with a stack overflow error once calls nest deeper than this
+    #[arg(long, default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
+    /// Fail once this many statements and expressions have been evaluated
+    #[arg(long)]
+    fuel: Option<u64>,
+
+    /// Fail once the input has run for this many milliseconds
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Fail once the strings, arrays and hashes of the input take more than this many bytes
+    #[arg(long)]
+    max_heap: Option<usize>,
+
+    /// Print the peak heap usage to stderr after running the input
+    #[arg(long)]
+    stats: bool,
+
+    /// Print the calls, time and allocations of every function to stderr after running the input
+    #[arg(long)]
+    profile: bool,
 
-    if args.input.len() > 0 {
-        let contents = fs::read_to_string(args.input).unwrap();
-        let lexer = MonkeyLexer::new(&contents);
-        let mut parser = monkey::parser::Parser::new(&lexer);
+    /// Write the profiled stacks to this file in the folded format of flame graph tools
+    #[arg(long, value_name = "FILE")]
+    profile_folded: Option<String>,
 
-        let program = parser.parse_program().unwrap();
+    /// Write the statements and branches the input ran to an lcov file, lcov.info unless given,
+    /// and print a summary to stderr
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "lcov.info", conflicts_with_all = ["profile", "profile_folded"])]
+    coverage: Option<String>,
 
-        let mut eval = Evaluate::new();
-        let out = eval.evaluate(&program);
+    /// Read the input as a syntax tree in JSON, as printed by parse --format json
+    #[arg(long)]
+    ast: bool,
 
-        println!("{}", out.unwrap());
+    /// Let the file system builtins access this directory and everything below it
+    #[arg(long, value_name = "DIR")]
+    allow_fs: Vec<PathBuf>,
+
+    /// Arguments the script finds in its args array, given after --
+    #[arg(last = true, value_name = "ARGS")]
+    args: Vec<String>,
+}
+
+/// How `parse` prints the syntax tree.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// One statement per line, as Rust debug output
+    Debug,
+    /// The whole program as JSON, which run --ast reads back
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a Monkey source file, - for stdin, printing the value of its last statement
+    Run {
+        file: String,
+
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    /// Evaluate an expression given on the command line
+    Eval {
+        #[arg(short, long = "expr", value_name = "SOURCE")]
+        expression: String,
+
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    /// Start the interactive REPL
+    Repl,
+    /// Print the tokens of a file, - for stdin, underlined below their source
+    Lex {
+        file: String,
+    },
+    /// Print the syntax tree of a file, - for stdin, one statement per line
+    Parse {
+        file: String,
+
+        #[arg(long, value_enum, default_value_t = Format::Debug)]
+        format: Format,
+    },
+    /// Format Monkey source files, printing the result unless --check or --write is given
+    Fmt {
+        files: Vec<String>,
+
+        /// Exit with status 1 if any file is not formatted
+        #[arg(long, conflicts_with = "write")]
+        check: bool,
+
+        /// Rewrite the files in place
+        #[arg(short, long)]
+        write: bool,
+    },
+    /// Report undefined names, unused bindings and other mistakes without running the files
+    Check {
+        files: Vec<String>,
+    },
+    /// Run a file under the step debugger, type help at the prompt for its commands
+    Debug {
+        file: String,
+
+        /// Pause whenever this line is reached instead of at the first statement
+        #[arg(short, long = "break")]
+        breakpoints: Vec<usize>,
+    },
+    /// Serve the Debug Adapter Protocol over stdin and stdout for editors
+    Dap,
+    /// Serve the Language Server Protocol over stdin and stdout for editors
+    Lsp,
+}
 
+fn debug(file: String, breakpoints: Vec<usize>) -> i32 {
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("{}: {}", file, err);
+            return 2;
+        }
+    };
+
+    let lexer = MonkeyLexer::new(&contents);
+    let program = match monkey::parser::Parser::new(&lexer).parse_program() {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{}: {}", file, err);
+            return 2;
+        }
+    };
+
+    let debugger = Rc::new(Debugger::new(&contents, &breakpoints, io::stdin().lock(), io::stdout()));
+    match Evaluate::new().with_hook(debugger).evaluate(&program) {
+        Ok(out) => {
+            println!("{}", out);
+            0
+        }
+        Err(Error::ExitError(code)) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+fn check(files: Vec<String>) -> i32 {
+    let mut status = 0;
+
+    for file in files {
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 2;
+                continue;
+            }
+        };
+
+        let diagnostics = match check_source(&contents) {
+            Ok(diagnostics) => diagnostics,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 2;
+                continue;
+            }
+        };
+
+        for diagnostic in diagnostics {
+            println!("{}:{}", file, diagnostic);
+            if diagnostic.severity == Severity::Error {
+                status = status.max(1);
+            }
+        }
+    }
+
+    status
+}
+
+fn fmt(files: Vec<String>, check: bool, write: bool) -> i32 {
+    let mut status = 0;
+
+    for file in files {
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 2;
+                continue;
+            }
+        };
+
+        let formatted = match format_source(&contents) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 2;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != contents {
+                println!("{}", file);
+                status = status.max(1);
+            }
+        } else if write {
+            if formatted != contents {
+                if let Err(err) = fs::write(&file, formatted) {
+                    eprintln!("{}: {}", file, err);
+                    status = 2;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+
+    status
+}
+
+/// Contents of `file`, reading stdin when it is `-`.
+fn read_source(file: &str) -> io::Result<String> {
+    if file == "-" {
+        io::read_to_string(io::stdin())
     } else {
-        let repl = Repl::new();
-        repl.start();
+        fs::read_to_string(file)
     }
 }
+
+/// Name `file` is reported under in errors and coverage.
+fn source_name(file: &str) -> &str {
+    if file == "-" { "<stdin>" } else { file }
+}
+
+fn parse(file: &str, format: Format) -> i32 {
+    let contents = match read_source(file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("{}: {}", source_name(file), err);
+            return 2;
+        }
+    };
+
+    let lexer = MonkeyLexer::new(&contents);
+    match monkey::parser::Parser::new(&lexer).parse_program() {
+        Ok(program) if format == Format::Json => match serde_json::to_string_pretty(&program) {
+            Ok(json) => {
+                println!("{}", json);
+                0
+            }
+            Err(err) => {
+                eprintln!("{}: {}", source_name(file), err);
+                1
+            }
+        },
+        Ok(program) => {
+            for statement in &program.statements {
+                println!("{:?}", statement);
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("{}: {}", source_name(file), err);
+            2
+        }
+    }
+}
+
+fn lex(file: &str) -> i32 {
+    let contents = match read_source(file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("{}: {}", source_name(file), err);
+            return 2;
+        }
+    };
+
+    match eval_lexer(&contents) {
+        Ok(tokens) => {
+            print!("{}", tokens);
+            0
+        }
+        Err(err) => {
+            eprintln!("{}: {}", source_name(file), err);
+            2
+        }
+    }
+}
+
+/// Run `contents`, source or a JSON syntax tree, exiting with 2 when it does not parse and 1 when evaluating it fails.
+fn run(name: &str, contents: &str, options: RunOptions) -> i32 {
+    let program = if options.ast {
+        serde_json::from_str(contents).map_err(|err| format!("Invalid syntax tree: {}", err))
+    } else {
+        let lexer = MonkeyLexer::new(contents);
+        monkey::parser::Parser::new(&lexer).parse_program().map_err(|err| err.to_string())
+    };
+    let mut program = match program {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("{}: {}", name, message);
+            return 2;
+        }
+    };
+    if options.optimize {
+        *program = optimize(*program);
+    }
+
+    let mut eval = Evaluate::new().with_max_depth(options.max_depth).with_args(options.args);
+    if let Some(fuel) = options.fuel {
+        eval = eval.with_fuel(fuel);
+    }
+    if let Some(timeout) = options.timeout {
+        eval = eval.with_deadline(Instant::now() + Duration::from_millis(timeout));
+    }
+    if let Some(max_heap) = options.max_heap {
+        eval = eval.with_max_heap(max_heap);
+    }
+    if !options.allow_fs.is_empty() {
+        let capabilities = options.allow_fs.into_iter().fold(Capabilities::default(), Capabilities::with_fs_root);
+        eval = eval.with_capabilities(capabilities);
+    }
+
+    let profiler = Rc::new(Profiler::new());
+    if options.profile || options.profile_folded.is_some() {
+        eval = eval.with_hook(profiler.clone());
+    }
+
+    let coverage = Rc::new(Coverage::new(&program));
+    if options.coverage.is_some() {
+        eval = eval.with_hook(coverage.clone());
+    }
+
+    let result = eval.global_environment().and_then(|env| eval.evaluate_program(&program, &env));
+    if options.stats {
+        eprintln!("peak heap: {} bytes", eval.peak_heap());
+    }
+    if options.profile || options.profile_folded.is_some() {
+        let stats = profiler.finish();
+        if options.profile {
+            eprint!("{}", profiler::table(&stats));
+        }
+        if let Some(path) = &options.profile_folded {
+            if let Err(err) = fs::write(path, profiler.folded()) {
+                eprintln!("{}: {}", path, err);
+            }
+        }
+    }
+    if let Some(path) = &options.coverage {
+        eprintln!("{}", coverage.summary(name));
+        if let Err(err) = fs::write(path, coverage.lcov(name)) {
+            eprintln!("{}: {}", path, err);
+        }
+    }
+
+    match result {
+        Ok(Object::Error(message)) => {
+            eprintln!("{}: {}", name, message);
+            1
+        }
+        Ok(out) => {
+            println!("{}", out);
+            0
+        }
+        Err(Error::ExitError(code)) => code,
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            1
+        }
+    }
+}
+
+/// Parse `source`, reporting the error under `name`.
+fn parse_source(name: &str, source: &str) -> Result<Box<Program>, i32> {
+    let lexer = MonkeyLexer::new(source);
+    monkey::parser::Parser::new(&lexer).parse_program().map_err(|err| {
+        eprintln!("{}: {}", name, err);
+        2
+    })
+}
+
+/// Evaluate `program` in `env`, printing its value unless it is null or bound by a `let`.
+fn run_line(name: &str, eval: &Evaluate, program: &Program, env: &Rc<RefCell<Environment>>, print: bool) -> Result<(), i32> {
+    let print = print && !matches!(program.statements.last(), Some(StatementNode::Let(..)));
+
+    match eval.evaluate_program(program, env) {
+        Ok(Object::Error(message)) => {
+            eprintln!("{}: {}", name, message);
+            Err(1)
+        }
+        Ok(Object::Null) => Ok(()),
+        Ok(Object::String(text)) if print => {
+            println!("{}", text);
+            Ok(())
+        }
+        Ok(out) if print => {
+            println!("{}", out);
+            Ok(())
+        }
+        Ok(_) => Ok(()),
+        Err(Error::ExitError(code)) => Err(code),
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            Err(1)
+        }
+    }
+}
+
+/// Evaluate `expression` for every line of stdin, between `begin` and `end`.
+fn lines(expression: &str, begin: Option<&str>, end: Option<&str>) -> Result<(), i32> {
+    let begin = begin.map(|begin| parse_source("<begin>", begin)).transpose()?;
+    let program = parse_source("<expr>", expression)?;
+    let end = end.map(|end| parse_source("<end>", end)).transpose()?;
+
+    let eval = Evaluate::new();
+    let env = eval.global_environment().map_err(|err| {
+        eprintln!("{}", err);
+        1
+    })?;
+
+    if let Some(begin) = &begin {
+        run_line("<begin>", &eval, begin, &env, false)?;
+    }
+
+    for (idx, line) in io::stdin().lines().enumerate() {
+        let line = line.map_err(|err| {
+            eprintln!("<stdin>: {}", err);
+            2
+        })?;
+
+        env.borrow_mut().store("line", &Object::String(line));
+        env.borrow_mut().store("nr", &Object::Int(idx as isize + 1));
+        run_line(&format!("<stdin>:{}", idx + 1), &eval, &program, &env, true)?;
+    }
+
+    if let Some(end) = &end {
+        run_line("<end>", &eval, end, &env, true)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if args.lines {
+        let expression = args.expression.unwrap_or_default();
+        let status = lines(&expression, args.begin.as_deref(), args.end.as_deref()).err().unwrap_or(0);
+        process::exit(status);
+    }
+
+    let status = match args.command {
+        Some(Command::Run { file, options }) => match read_source(&file) {
+            Ok(contents) => run(source_name(&file), &contents, options),
+            Err(err) => {
+                eprintln!("{}: {}", source_name(&file), err);
+                2
+            }
+        },
+        Some(Command::Eval { expression, options }) => run("<expr>", &expression, options),
+        Some(Command::Lex { file }) => lex(&file),
+        Some(Command::Parse { file, format }) => parse(&file, format),
+        Some(Command::Fmt { files, check, write }) => fmt(files, check, write),
+        Some(Command::Check { files }) => check(files),
+        Some(Command::Debug { file, breakpoints }) => debug(file, breakpoints),
+        Some(Command::Dap) => {
+            dap::serve(io::stdin().lock(), io::stdout());
+            0
+        }
+        Some(Command::Lsp) => {
+            lsp::serve(io::stdin().lock(), io::stdout());
+            0
+        }
+        Some(Command::Repl) | None => {
+            Repl::new().start();
+            0
+        }
+    };
+
+    process::exit(status);
+}
@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use crate::monkey::interpreter::Environment;
+use crate::monkey::interpreter::object::Object;
+use crate::monkey::parser::ast::{ExpressionNode, Identifier, Program, StatementNode, Visitor};
+use crate::monkey::parser::ast::visit::{walk_expression, walk_statement};
+use crate::monkey::lexer::MonkeyLexer;
+use crate::monkey::parser::Parser;
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// `line:column: severity: message` with lines and columns counted from 1.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}: {}", self.span.line_start + 1, self.span.start + 1, self.severity, self.message)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BindingKind {
+    BuiltIn,
+    Param,
+    Let,
+}
+
+/// A name a program binds, or one of the builtins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: BindingKind,
+    /// Where the name is bound, the default span for builtins.
+    pub span: Span,
+    /// Number of parameters when the binding is known to be a function.
+    pub arity: Option<usize>,
+    /// Parameter names when the binding is a function literal, `_` for those of builtins and
+    /// `_?` for the optional ones.
+    pub params: Option<Vec<String>>,
+}
+
+/// Use of a name along with the index of the definition it resolves to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    pub definition: Option<usize>,
+}
+
+/// Everything the checker learned about a program.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+struct Binding {
+    name: String,
+    kind: BindingKind,
+    span: Span,
+    used: bool,
+    /// Number of parameters when the binding is known to be a function.
+    arity: Option<usize>,
+    /// Index into `Checker::definitions`.
+    definition: usize,
+}
+
+/// Bindings of one function body - or of the script itself - as `if` blocks share their scope.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+    /// Latest binding of every name defined so far.
+    names: HashMap<String, usize>,
+    /// Every name the body defines with `let`, nested functions may use them ahead of the definition.
+    hoisted: HashSet<String>,
+    /// Names nested functions used ahead of their definition, with the references using them.
+    pending: HashMap<String, Vec<usize>>,
+}
+
+/// Resolve the names of a program without running it.
+pub struct Checker {
+    scopes: Vec<Scope>,
+    /// Span of the statement being checked, for nodes without one of their own.
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+}
+
+/// Check `program`, returning the diagnostics in source order.
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    analyze(program).diagnostics
+}
+
+/// Check `program`, also returning what each name resolves to.
+pub fn analyze(program: &Program) -> Analysis {
+    let mut checker = Checker::new();
+    checker.function(&[], program);
+
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.byte_start);
+
+    Analysis { diagnostics, definitions: checker.definitions, references: checker.references }
+}
+
+/// Parse `source` the way it is run and check it.
+pub fn check_source(source: &str) -> Result<Vec<Diagnostic>> {
+    let lexer = MonkeyLexer::new(source);
+    let program = Parser::new(&lexer).parse_program()?;
+    Ok(check(&program))
+}
+
+impl Checker {
+    fn new() -> Self {
+        let mut builtins = Scope::default();
+        let mut definitions = Vec::new();
+
+        let env = Environment::new();
+        let mut names: Vec<_> = env.bindings().collect();
+        names.sort_by_key(|(name, _)| name.as_str());
+        for (name, object) in names {
+            let arity = match object {
+                // Builtins taking optional arguments are not checked.
+                Object::BuiltIn { params, optional: 0, .. } => Some(*params),
+                _ => None,
+            };
+            let params = match object {
+                Object::BuiltIn { params, optional, .. } => {
+                    let mut names = vec!["_".to_string(); params - optional];
+                    names.extend(vec!["_?".to_string(); *optional]);
+                    Some(names)
+                }
+                _ => None,
+            };
+
+            builtins.names.insert(name.clone(), builtins.bindings.len());
+            builtins.bindings.push(Binding {
+                name: name.clone(),
+                kind: BindingKind::BuiltIn,
+                span: Span::default(),
+                used: true,
+                arity,
+                definition: definitions.len(),
+            });
+            definitions.push(Definition {
+                name: name.clone(),
+                kind: BindingKind::BuiltIn,
+                span: Span::default(),
+                arity,
+                params,
+            });
+        }
+
+        Checker {
+            scopes: vec![builtins],
+            span: Span::default(),
+            diagnostics: Vec::new(),
+            definitions,
+            references: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, severity: Severity, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic { severity, message, span });
+    }
+
+    /// Check a function body in a scope of its own.
+    fn function(&mut self, params: &[Identifier], body: &Program) {
+        let mut lets = Lets::default();
+        lets.visit_program(body);
+
+        self.scopes.push(Scope { hoisted: lets.0, ..Scope::default() });
+        for param in params {
+            self.declare(param, BindingKind::Param, None, None);
+        }
+
+        self.visit_program(body);
+
+        let scope = self.scopes.pop().unwrap();
+        for binding in scope.bindings {
+            if binding.kind == BindingKind::Let && !binding.used && !binding.name.starts_with('_') {
+                self.report(Severity::Warning, format!("`{}` is never used", binding.name), binding.span);
+            }
+        }
+    }
+
+    fn declare(&mut self, identifier: &Identifier, kind: BindingKind, arity: Option<usize>, params: Option<Vec<String>>) {
+        let name = &identifier.0;
+
+        let innermost = self.scopes.len() - 1;
+        let (outer, scope) = self.scopes.split_at_mut(innermost);
+        let scope = &mut scope[0];
+
+        let shadowed = if let Some(&idx) = scope.names.get(name) {
+            Some(format!("`{}` shadows the binding on line {}", name, scope.bindings[idx].span.line_start + 1))
+        } else if let Some(binding) = outer.iter().rev().find_map(|scope| scope.names.get(name).map(|&idx| &scope.bindings[idx])) {
+            Some(match binding.kind {
+                BindingKind::BuiltIn => format!("`{}` shadows the builtin function", name),
+                _ => format!("`{}` shadows the binding on line {}", name, binding.span.line_start + 1),
+            })
+        } else if outer.iter().any(|scope| scope.hoisted.contains(name)) {
+            Some(format!("`{}` shadows a binding of the enclosing scope", name))
+        } else {
+            None
+        };
+
+        let definition = self.definitions.len();
+        self.definitions.push(Definition { name: name.clone(), kind, span: identifier.1.clone(), arity, params });
+
+        let pending = scope.pending.remove(name);
+        for &reference in pending.iter().flatten() {
+            self.references[reference].definition = Some(definition);
+        }
+
+        scope.names.insert(name.clone(), scope.bindings.len());
+        scope.bindings.push(Binding { name: name.clone(), kind, span: identifier.1.clone(), used: pending.is_some(), arity, definition });
+
+        if let Some(message) = shadowed {
+            self.report(Severity::Warning, message, identifier.1.clone());
+        }
+    }
+
+    /// Mark the binding `identifier` refers to as used, returning its arity when known.
+    fn resolve(&mut self, identifier: &Identifier) -> Option<usize> {
+        let name = &identifier.0;
+        let innermost = self.scopes.len() - 1;
+
+        let reference = self.references.len();
+        self.references.push(Reference { span: identifier.1.clone(), definition: None });
+
+        for (depth, scope) in self.scopes.iter_mut().enumerate().rev() {
+            if let Some(&idx) = scope.names.get(name) {
+                let binding = &mut scope.bindings[idx];
+                binding.used = true;
+                self.references[reference].definition = Some(binding.definition);
+                return binding.arity;
+            }
+
+            // Functions run after the enclosing body defined the rest of its bindings.
+            if depth != innermost && scope.hoisted.contains(name) {
+                scope.pending.entry(name.clone()).or_default().push(reference);
+                return None;
+            }
+        }
+
+        self.report(Severity::Error, format!("undefined identifier `{}`", name), identifier.1.clone());
+        None
+    }
+
+    fn call(&mut self, function: &ExpressionNode, params: &[Box<ExpressionNode>]) {
+        let (arity, name, span) = match function {
+            ExpressionNode::Identifier(identifier) => {
+                (self.resolve(identifier), format!("`{}`", identifier.0), identifier.1.clone())
+            }
+            ExpressionNode::Function { params, .. } => {
+                self.visit_expression(function);
+                (Some(params.len()), "function".to_string(), self.span.clone())
+            }
+            _ => {
+                self.visit_expression(function);
+                (None, String::new(), self.span.clone())
+            }
+        };
+
+        if let Some(arity) = arity {
+            if arity != params.len() {
+                let message = format!("{} takes {} argument(s) but {} were given", name, arity, params.len());
+                self.report(Severity::Error, message, span);
+            }
+        }
+
+        for param in params {
+            self.visit_expression(param);
+        }
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_program(&mut self, program: &Program) {
+        let outer = self.span.clone();
+
+        for (idx, statement) in program.statements.iter().enumerate() {
+            self.span = program.span(idx).cloned().unwrap_or_default();
+
+            if idx > 0 && matches!(program.statements[idx - 1], StatementNode::Return(_)) {
+                self.report(Severity::Warning, "unreachable statement".to_string(), self.span.clone());
+            }
+
+            self.visit_statement(statement);
+        }
+
+        self.span = outer;
+    }
+
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        match statement {
+            StatementNode::Let(identifier, expression) => {
+                self.visit_expression(expression);
+
+                let params = match expression.as_ref() {
+                    ExpressionNode::Function { params, .. } => Some(params.iter().map(|param| param.0.clone()).collect::<Vec<_>>()),
+                    _ => None,
+                };
+                self.declare(identifier, BindingKind::Let, params.as_ref().map(Vec::len), params);
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionNode) {
+        match expression {
+            ExpressionNode::Identifier(identifier) => {
+                self.resolve(identifier);
+            }
+            ExpressionNode::Function { params, body, .. } => self.function(params, body),
+            ExpressionNode::Call { function, params } => self.call(function, params),
+            _ => walk_expression(self, expression),
+        }
+    }
+}
+
+/// Names bound by `let` in a body, not looking into nested functions.
+#[derive(Default)]
+struct Lets(HashSet<String>);
+
+impl Visitor for Lets {
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        if let StatementNode::Let(identifier, _) = statement {
+            self.0.insert(identifier.0.clone());
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionNode) {
+        if !matches!(expression, ExpressionNode::Function { .. }) {
+            walk_expression(self, expression)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_checker() {
+        let contents = fs::read_to_string("monkey/test_checker.mky").unwrap();
+
+        let mut output = String::new();
+        for diagnostic in check_source(&contents).unwrap() {
+            output += &format!("{}\n", diagnostic);
+        }
+
+        insta::assert_snapshot!(output)
+    }
+
+    #[test]
+    fn test_checker_clean() {
+        let contents = fs::read_to_string("example/fibonacci.mky").unwrap();
+
+        assert_eq!(check_source(&contents).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_checker_agrees_with_run() {
+        for source in ["let h = {}; h", "let x = (1 + 2; x", "let 5 = 3; 1"] {
+            let lexer = MonkeyLexer::new(source);
+            let parsed = Parser::new(&lexer).parse_program();
+            assert_eq!(check_source(source).is_ok(), parsed.is_ok(), "{}", source);
+        }
+    }
+}
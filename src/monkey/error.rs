@@ -1,6 +1,7 @@
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 use thiserror::Error;
-use crate::monkey::token::Token;
+use crate::monkey::token::{Span, Token};
 
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum Error {
@@ -8,8 +9,51 @@ pub enum Error {
     TodoError(String),
 
     #[error("Invalid token found at {0:?}")]
-    InvalidTokenError(Rc<Token>),
+    InvalidTokenError(Rc<Token<'static>>),
 
     #[error("Invalid escape char {0:?}")]
     InvalidCharError(char),
+
+    #[error("Invalid escape sequence: {0}")]
+    InvalidEscapeError(String),
+
+    #[error("Unterminated string starting at {0:?}")]
+    UnterminatedStringError(Span),
+
+    /// `frames` lists the innermost calls first.
+    #[error("Stack overflow: more than {max_depth} nested calls\n{}", .frames.join("\n"))]
+    StackOverflowError { max_depth: usize, frames: Vec<String> },
+
+    #[error("Budget exhausted: {0}")]
+    BudgetExhaustedError(Budget),
+
+    #[error("Stopped by the debugger")]
+    AbortedError,
+
+    #[error("Permission denied: {0}")]
+    PermissionDeniedError(String),
+
+    /// Raised by the `exit` builtin, unwinding the script with its status.
+    #[error("Exited with status {0}")]
+    ExitError(i32),
+}
+
+/// Limit on the work an evaluation may do.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Budget {
+    /// Number of statements and expressions allowed.
+    Fuel(u64),
+    Deadline,
+    /// Estimated bytes of strings, arrays and hashes alive at once.
+    Heap(usize),
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Budget::Fuel(fuel) => write!(f, "used all {} fuel", fuel),
+            Budget::Deadline => write!(f, "ran past the deadline"),
+            Budget::Heap(bytes) => write!(f, "allocated more than {} bytes", bytes),
+        }
+    }
 }
\ No newline at end of file
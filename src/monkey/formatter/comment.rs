@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use crate::monkey::error::Error;
+use crate::monkey::parser::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::monkey::Result;
+use crate::monkey::token::{Token, TokenType, TriviaKind};
+
+/// Where a comment goes, relative to the statements around it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    /// Before the statement starting at this byte.
+    Before(usize),
+    /// After the statement starting at this byte, the last of its block.
+    After(usize),
+    /// After the last statement of the program.
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    /// Written on a line of its own rather than after the code before it.
+    pub own_line: bool,
+}
+
+/// Comments of the tree under `root` by where they go.
+///
+/// Only comments between statements have a place in the formatted output, any other fails.
+pub fn collect(root: &SyntaxNode) -> Result<HashMap<Anchor, Vec<Comment>>> {
+    let mut comments = HashMap::new();
+    walk(root, None, &mut comments)?;
+    Ok(comments)
+}
+
+/// Collect the comments below `node`, part of the statement starting at `statement` if any.
+fn walk(node: &SyntaxNode, statement: Option<usize>, comments: &mut HashMap<Anchor, Vec<Comment>>) -> Result<()> {
+    let holds_statements = matches!(node.kind, SyntaxKind::Program | SyntaxKind::Block);
+    let last = node.nodes().last().map(|last| last.span().byte_start);
+
+    for child in &node.children {
+        match child {
+            SyntaxElement::Node(child) if holds_statements => walk(child, Some(child.span().byte_start), comments)?,
+            SyntaxElement::Node(child) => walk(child, statement, comments)?,
+            SyntaxElement::Token(token) => {
+                let found = token_comments(token);
+                if found.is_empty() {
+                    continue;
+                }
+
+                let anchor = match (node.kind, token.token_type, last) {
+                    _ if statement == Some(token.span.byte_start) => Anchor::Before(token.span.byte_start),
+                    (SyntaxKind::Program, TokenType::EndOfFile, _) => Anchor::End,
+                    (SyntaxKind::Block, TokenType::RightBrace, Some(last)) => Anchor::After(last),
+                    _ => return Err(Error::TodoError(format!("comments within expressions can not be formatted, found one before {:?}", token))),
+                };
+                comments.entry(anchor).or_default().extend(found);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn token_comments(token: &Token) -> Vec<Comment> {
+    // Nothing comes before the trivia at the start of the file.
+    let mut own_line = token.span.byte_start == token.trivia.len();
+
+    let mut result = Vec::new();
+    for trivia in token.leading_trivia() {
+        match trivia.kind {
+            TriviaKind::Whitespace => own_line |= trivia.text.contains('\n'),
+            TriviaKind::Comment => result.push(Comment { text: trivia.text.trim_end().to_string(), own_line }),
+        }
+    }
+
+    result
+}
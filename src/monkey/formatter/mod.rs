@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::monkey::formatter::comment::{Anchor, Comment};
+use crate::monkey::parser::ast::{ExpressionNode, Operator, Program, StatementNode};
+use crate::monkey::parser::cst::SyntaxTree;
+use crate::monkey::parser::expression::OrderOfOps;
+use crate::monkey::Result;
+
+pub mod comment;
+
+/// Pretty printer turning a `Program` back into canonical Monkey source.
+///
+/// Parsing the output always yields a `Program` equal to the one formatted.
+pub struct Formatter {
+    /// Preferred maximum line length - only lists are wrapped to honor it.
+    width: usize,
+    indent: usize,
+    /// Comments to write between the statements, see `comment::collect`.
+    comments: Rc<HashMap<Anchor, Vec<Comment>>>,
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Formatter {
+            width: 80,
+            indent: 4,
+            comments: Rc::default(),
+        }
+    }
+
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_comments(mut self, comments: HashMap<Anchor, Vec<Comment>>) -> Self {
+        self.comments = Rc::new(comments);
+        self
+    }
+
+    pub fn format(&self, program: &Program) -> String {
+        let mut output = String::new();
+
+        let mut previous_multiline = false;
+        for (idx, statement) in program.statements.iter().enumerate() {
+            let text = self.statement(statement, 0, false);
+            let multiline = text.contains('\n');
+
+            let comments = self.comments_at(program, idx, Anchor::Before);
+            self.write_trailing(&mut output, comments);
+
+            // Keep multi-line statements such as function definitions visually apart.
+            if idx > 0 && (multiline || previous_multiline) {
+                output += "\n";
+            }
+            self.write_own_lines(&mut output, comments, 0);
+            output += &text;
+            output += "\n";
+
+            previous_multiline = multiline;
+        }
+
+        let comments = self.comments.get(&Anchor::End).map(Vec::as_slice).unwrap_or_default();
+        self.write_trailing(&mut output, comments);
+        self.write_own_lines(&mut output, comments, 0);
+
+        output
+    }
+
+    /// Comments anchored by `anchor` to the statement `idx` of `program`.
+    fn comments_at(&self, program: &Program, idx: usize, anchor: fn(usize) -> Anchor) -> &[Comment] {
+        program.span(idx)
+            .and_then(|span| self.comments.get(&anchor(span.byte_start)))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Append the comments following code on its line to the last line of `output`.
+    fn write_trailing(&self, output: &mut String, comments: &[Comment]) {
+        for comment in comments.iter().filter(|comment| !comment.own_line) {
+            let newline = output.ends_with('\n');
+            if newline {
+                output.pop();
+            }
+            *output += " ";
+            *output += &comment.text;
+            if newline {
+                *output += "\n";
+            }
+        }
+    }
+
+    /// Write the comments on lines of their own, indented to `level`.
+    fn write_own_lines(&self, output: &mut String, comments: &[Comment], level: usize) {
+        for comment in comments.iter().filter(|comment| comment.own_line) {
+            *output += &self.pad(level);
+            *output += &comment.text;
+            *output += "\n";
+        }
+    }
+
+    /// Whether comments are anchored to the statement `idx` of `program`.
+    fn has_comments(&self, program: &Program, idx: usize) -> bool {
+        !self.comments_at(program, idx, Anchor::Before).is_empty() || !self.comments_at(program, idx, Anchor::After).is_empty()
+    }
+
+    fn pad(&self, level: usize) -> String {
+        " ".repeat(level * self.indent)
+    }
+
+    /// Format a statement starting at the indentation of `level`.
+    ///
+    /// `block_value` marks the last statement of a block, whose expression is the block's value
+    /// and is written without a trailing `;`.
+    fn statement(&self, statement: &StatementNode, level: usize, block_value: bool) -> String {
+        let column = level * self.indent;
+        match statement {
+            StatementNode::Let(ident, expression) => {
+                let prefix = format!("let {} = ", ident.0);
+                let value = self.expression(expression, level, column + prefix.len());
+                format!("{}{};", prefix, value)
+            }
+            StatementNode::Return(expression) => {
+                format!("return {};", self.expression(expression, level, column + 7))
+            }
+            StatementNode::If { condition, consequence, alternative } => {
+                self.if_expression(condition, consequence, alternative, level, column, false)
+            }
+            StatementNode::Expression { expression } => {
+                let mut text = self.expression(expression, level, column);
+                // At the start of a statement `if` is parsed as an if statement.
+                if starts_with_if(&text) {
+                    text = format!("({})", self.expression(expression, level, column + 1));
+                }
+
+                if block_value {
+                    text
+                } else {
+                    text + ";"
+                }
+            }
+        }
+    }
+
+    /// Format a block as `{ ... }` - single expressions stay on one line when `inline` allows it.
+    fn block(&self, program: &Program, level: usize, column: usize, inline: bool) -> String {
+        if program.statements.is_empty() {
+            return "{}".to_string();
+        }
+
+        if let [StatementNode::Expression { expression }] = program.statements.as_slice() {
+            if inline && !self.has_comments(program, 0) {
+                let text = self.expression(expression, level + 1, column + 2);
+                if !text.contains('\n') && !starts_with_if(&text) && column + text.chars().count() + 4 <= self.width {
+                    return format!("{{ {} }}", text);
+                }
+            }
+        }
+
+        let mut output = "{\n".to_string();
+        let last = program.statements.len() - 1;
+        for (idx, statement) in program.statements.iter().enumerate() {
+            let comments = self.comments_at(program, idx, Anchor::Before);
+            self.write_trailing(&mut output, comments);
+            self.write_own_lines(&mut output, comments, level + 1);
+
+            output += &self.pad(level + 1);
+            output += &self.statement(statement, level + 1, idx == last);
+            output += "\n";
+        }
+        let comments = self.comments_at(program, last, Anchor::After);
+        self.write_trailing(&mut output, comments);
+        self.write_own_lines(&mut output, comments, level + 1);
+        output += &self.pad(level);
+        output += "}";
+
+        output
+    }
+
+    fn if_expression(
+        &self,
+        condition: &ExpressionNode,
+        consequence: &Program,
+        alternative: &Option<Box<Program>>,
+        level: usize,
+        column: usize,
+        inline: bool,
+    ) -> String {
+        let mut output = "if (".to_string();
+        output += &self.expression(condition, level, column + output.len());
+        output += ") ";
+        output += &self.block(consequence, level, column + output.len(), inline);
+
+        if let Some(alternative) = alternative {
+            output += " else ";
+            let column = last_line_width(&output, column);
+            match alternative.statements.as_slice() {
+                [StatementNode::If { condition, consequence, alternative }] => {
+                    output += &self.if_expression(condition, consequence, alternative, level, column, inline);
+                }
+                _ => output += &self.block(alternative, level, column, inline),
+            }
+        }
+
+        output
+    }
+
+    /// Format an expression whose first character is written at `column`.
+    fn expression(&self, expression: &ExpressionNode, level: usize, column: usize) -> String {
+        match expression {
+            ExpressionNode::Identifier(ident) => ident.0.clone(),
+            ExpressionNode::Bool(val) => val.to_string(),
+            ExpressionNode::Int(val) => val.to_string(),
+            ExpressionNode::String(val) => format!("\"{}\"", escape(val)),
+            ExpressionNode::InterpolatedString { parts } => {
+                // A string made only of string parts must keep one of them embedded to stay interpolated.
+                let embed_all = parts.iter().all(|part| matches!(part, ExpressionNode::String(_)));
+
+                let mut output = "\"".to_string();
+                for part in parts {
+                    match part {
+                        ExpressionNode::String(val) if !embed_all => output += &escape(val),
+                        _ => {
+                            output += "${";
+                            output += &self.expression(part, level, column + output.len());
+                            output += "}";
+                        }
+                    }
+                }
+                output += "\"";
+
+                output
+            }
+            ExpressionNode::Prefix { operator, expression } => {
+                let symbol = operator_symbol(operator);
+                format!("{}{}", symbol, self.operand(expression, OrderOfOps::Prefix as usize, level, column + 1))
+            }
+            ExpressionNode::Infix { operator, left, right } => {
+                let precedence = operator_precedence(operator) as usize;
+
+                let mut output = self.operand(left, precedence, level, column);
+                output += &format!(" {} ", operator_symbol(operator));
+                let right_column = last_line_width(&output, column);
+                output += &self.operand(right, precedence + 1, level, right_column);
+
+                output
+            }
+            ExpressionNode::Call { function, params } => {
+                let mut output = self.operand(function, OrderOfOps::Call as usize, level, column);
+                let column = last_line_width(&output, column);
+                output += &self.list("(", ")", params, level, column, |formatter, param, level, column| {
+                    formatter.expression(param, level, column)
+                });
+
+                output
+            }
+            ExpressionNode::Index { left, index } => {
+                let mut output = self.operand(left, OrderOfOps::Call as usize, level, column);
+                output += "[";
+                let column = last_line_width(&output, column);
+                output += &self.expression(index, level, column);
+                output += "]";
+
+                output
+            }
+            ExpressionNode::Function { params, body, .. } => {
+                let names: Vec<&str> = params.iter().map(|param| param.0.as_str()).collect();
+                let header = format!("fn({}) ", names.join(", "));
+                let column = column + header.chars().count();
+                header + &self.block(body, level, column, true)
+            }
+            ExpressionNode::ArrayLiteral { params } => {
+                self.list("[", "]", params, level, column, |formatter, param, level, column| {
+                    formatter.expression(param, level, column)
+                })
+            }
+            ExpressionNode::HashLiteral { params } => {
+                self.list("{", "}", params, level, column, |formatter, (key, val), level, column| {
+                    let mut output = formatter.expression(key, level, column);
+                    output += ": ";
+                    let column = last_line_width(&output, column);
+                    output += &formatter.expression(val, level, column);
+                    output
+                })
+            }
+            ExpressionNode::If { condition, consequence, alternative } => {
+                self.if_expression(condition, consequence, alternative, level, column, true)
+            }
+        }
+    }
+
+    /// Format an operand, adding parentheses when it binds looser than `precedence`.
+    fn operand(&self, expression: &ExpressionNode, precedence: usize, level: usize, column: usize) -> String {
+        if expression_precedence(expression) < precedence {
+            format!("({})", self.expression(expression, level, column + 1))
+        } else {
+            self.expression(expression, level, column)
+        }
+    }
+
+    /// Format a comma separated list, putting every item on its own line when it does not fit.
+    fn list<T>(
+        &self,
+        open: &str,
+        close: &str,
+        items: &[T],
+        level: usize,
+        column: usize,
+        render: impl Fn(&Formatter, &T, usize, usize) -> String,
+    ) -> String {
+        if items.is_empty() {
+            return format!("{}{}", open, close);
+        }
+
+        // Lists nested inside a list that fits are never wrapped.
+        let unbounded = Formatter { width: usize::MAX, indent: self.indent, comments: Rc::clone(&self.comments) };
+        let flat = unbounded.join(open, close, items, level, column, &render);
+        if !flat.contains('\n') && column + flat.chars().count() <= self.width {
+            return flat;
+        }
+
+        // Trailing blocks (`f(x, fn() { ... })`) may span lines as long as the first one fits.
+        if flat.contains('\n') {
+            let flat = self.join(open, close, items, level, column, &render);
+            let first_line = flat.lines().next().unwrap_or_default();
+            if column + first_line.chars().count() <= self.width {
+                return flat;
+            }
+        }
+
+        let mut output = format!("{}\n", open);
+        for (idx, item) in items.iter().enumerate() {
+            output += &self.pad(level + 1);
+            output += &render(self, item, level + 1, (level + 1) * self.indent);
+            if idx + 1 < items.len() {
+                output += ",";
+            }
+            output += "\n";
+        }
+        output += &self.pad(level);
+        output += close;
+
+        output
+    }
+
+    fn join<T>(
+        &self,
+        open: &str,
+        close: &str,
+        items: &[T],
+        level: usize,
+        column: usize,
+        render: &impl Fn(&Formatter, &T, usize, usize) -> String,
+    ) -> String {
+        let mut output = open.to_string();
+        for (idx, item) in items.iter().enumerate() {
+            if idx > 0 {
+                output += ", ";
+            }
+            let item_column = last_line_width(&output, column);
+            output += &render(self, item, level, item_column);
+        }
+        output += close;
+
+        output
+    }
+}
+
+/// Parse `source` and format it, keeping the comments between statements.
+///
+/// Sources with comments anywhere else are refused as the output has no place to keep them.
+pub fn format_source(source: &str) -> Result<String> {
+    let tree = SyntaxTree::parse(source)?;
+    let comments = comment::collect(&tree.root)?;
+
+    Ok(Formatter::new().with_comments(comments).format(&tree.to_program()?))
+}
+
+/// Column reached after writing `text` starting at `column`.
+fn last_line_width(text: &str, column: usize) -> usize {
+    match text.rfind('\n') {
+        Some(idx) => text[idx + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+fn starts_with_if(text: &str) -> bool {
+    text.starts_with("if (")
+}
+
+fn operator_precedence(operator: &Operator) -> OrderOfOps {
+    match operator {
+        Operator::Equal | Operator::NotEqual => OrderOfOps::Equals,
+        Operator::Less | Operator::Greater => OrderOfOps::LessGreater,
+        Operator::Add | Operator::Sub => OrderOfOps::Sum,
+        Operator::Mul | Operator::Div => OrderOfOps::Product,
+        Operator::Not | Operator::Neg => OrderOfOps::Prefix,
+        Operator::Call => OrderOfOps::Call,
+        Operator::Index => OrderOfOps::Index,
+    }
+}
+
+fn expression_precedence(expression: &ExpressionNode) -> usize {
+    match expression {
+        ExpressionNode::Infix { operator, .. } => operator_precedence(operator) as usize,
+        // Negative literals only come from optimizations and print as a prefix `-`.
+        ExpressionNode::Prefix { .. } | ExpressionNode::Int(..0) => OrderOfOps::Prefix as usize,
+        ExpressionNode::Call { .. } => OrderOfOps::Call as usize,
+        ExpressionNode::Function { .. } | ExpressionNode::If { .. } => OrderOfOps::Lowest as usize,
+        _ => OrderOfOps::Index as usize,
+    }
+}
+
+fn operator_symbol(operator: &Operator) -> &'static str {
+    match operator {
+        Operator::Not => "!",
+        Operator::Neg => "-",
+        Operator::Add => "+",
+        Operator::Sub => "-",
+        Operator::Mul => "*",
+        Operator::Div => "/",
+        Operator::Greater => ">",
+        Operator::Less => "<",
+        Operator::Equal => "==",
+        Operator::NotEqual => "!=",
+        Operator::Call => "()",
+        Operator::Index => "[]",
+    }
+}
+
+/// Escape a string literal so the lexer reads back the exact same value.
+fn escape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => output += "\\\"",
+            '\\' => output += "\\\\",
+            '\n' => output += "\\n",
+            '\t' => output += "\\t",
+            '\r' => output += "\\r",
+            '\0' => output += "\\0",
+            '$' if chars.peek() == Some(&'{') => output += "\\$",
+            ch if ch.is_control() => output += &format!("\\u{{{:X}}}", ch as u32),
+            ch => output.push(ch),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::monkey::error::Error;
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> Box<Program> {
+        let lexer = MonkeyLexer::new(source);
+        let mut parser = Parser::new(&lexer);
+        parser.parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_formatter() {
+        let contents = fs::read_to_string("monkey/test_formatter.mky").unwrap();
+
+        insta::assert_snapshot!(format_source(&contents).unwrap())
+    }
+
+    #[test]
+    fn test_formatter_width() {
+        let source = "let list = [first_item, second_item, third_item]; f(alpha, [1, 2, 3], beta);";
+        let program = parse(source);
+
+        insta::assert_snapshot!(Formatter::new().with_width(30).format(&program))
+    }
+
+    #[test]
+    fn test_formatter_comments() {
+        let contents = fs::read_to_string("monkey/test_formatter_comments.mky").unwrap();
+        let formatted = format_source(&contents).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        insta::assert_snapshot!(formatted);
+
+        // Within an expression there is no line to keep the comment on.
+        let error = format_source("let a = [1, // one\n  2];").err().unwrap();
+        assert!(matches!(error, Error::TodoError(_)));
+    }
+
+    #[test]
+    fn test_formatter_round_trip() {
+        let mut files: Vec<_> = fs::read_dir("monkey").unwrap()
+            .chain(fs::read_dir("example").unwrap())
+            .map(|entry| entry.unwrap().path())
+            // Lexer fixtures are not valid programs.
+            .filter(|path| !path.to_string_lossy().contains("lexer"))
+            .collect();
+        files.sort();
+
+        for file in files {
+            let contents = fs::read_to_string(&file).unwrap();
+            let program = parse(&contents);
+
+            let formatted = Formatter::new().format(&program);
+            assert_eq!(parse(&formatted), program, "{:?} does not round trip:\n{}", file, formatted);
+            assert_eq!(format_source(&formatted).unwrap(), formatted, "{:?} is not stable", file);
+        }
+    }
+}
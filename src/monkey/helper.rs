@@ -1,5 +1,13 @@
+use unicode_xid::UnicodeXID;
+
+/// Character allowed to start an identifier (Unicode `XID_Start` or `_`).
 pub fn is_identifier(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+    ch == '_' || ch.is_xid_start()
+}
+
+/// Character allowed after the first one of an identifier (Unicode `XID_Continue`).
+pub fn is_identifier_continue(ch: char) -> bool {
+    ch.is_xid_continue()
 }
 
 pub fn is_digit(ch: char) -> bool {
@@ -18,8 +26,21 @@ mod test {
     fn test_is_identifier() {
         assert_eq!(is_identifier('a'), true);
         assert_eq!(is_identifier('_'), true);
-        assert_eq!(is_identifier('あ'), false);
+        assert_eq!(is_identifier('あ'), true);
+        assert_eq!(is_identifier('é'), true);
         assert_eq!(is_identifier('1'), false);
+        assert_eq!(is_identifier('$'), false);
+        assert_eq!(is_identifier('😀'), false);
+    }
+
+    #[test]
+    fn test_is_identifier_continue() {
+        assert_eq!(is_identifier_continue('a'), true);
+        assert_eq!(is_identifier_continue('_'), true);
+        assert_eq!(is_identifier_continue('1'), true);
+        assert_eq!(is_identifier_continue('あ'), true);
+        assert_eq!(is_identifier_continue('-'), false);
+        assert_eq!(is_identifier_continue(' '), false);
     }
 
     #[test]
@@ -40,4 +61,4 @@ mod test {
         assert_eq!(is_whitespace('\t'), true);
         assert_eq!(is_whitespace('　'), true); // non-ascii space
     }
-}
\ No newline at end of file
+}
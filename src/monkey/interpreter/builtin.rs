@@ -1,8 +1,15 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
 use std::fmt::format;
+use serde_json::{Map, Value};
+use crate::monkey::error::Error;
+use crate::monkey::interpreter::capability::Capabilities;
 use crate::monkey::interpreter::{NULL, Object};
 use crate::monkey::Result;
 
-pub fn str_len(objs: Vec<Object>) -> Result<Object> {
+pub fn str_len(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     let Some(obj) = objs.first() else {
         return Ok(Object::Error(format!("len(): Invalid argument")))
     };
@@ -14,7 +21,7 @@ pub fn str_len(objs: Vec<Object>) -> Result<Object> {
     })
 }
 
-pub fn first(objs: Vec<Object>) -> Result<Object> {
+pub fn first(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     let Some(obj) = objs.first() else {
         return Ok(Object::Error(format!("first(): Invalid argument")))
     };
@@ -28,7 +35,7 @@ pub fn first(objs: Vec<Object>) -> Result<Object> {
     })
 }
 
-pub fn last(objs: Vec<Object>) -> Result<Object> {
+pub fn last(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     let Some(obj) = objs.first() else {
         return Ok(Object::Error(format!("last(): Invalid argument")))
     };
@@ -42,7 +49,7 @@ pub fn last(objs: Vec<Object>) -> Result<Object> {
     })
 }
 
-pub fn push(objs: Vec<Object>) -> Result<Object> {
+pub fn push(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     if objs.len() != 2 {
         return Ok(Object::Error(format!("push(): Invalid argument")))
     }
@@ -61,7 +68,7 @@ pub fn push(objs: Vec<Object>) -> Result<Object> {
     })
 }
 
-pub fn rest(objs: Vec<Object>) -> Result<Object> {
+pub fn rest(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     let Some(obj) = objs.first() else {
         return Ok(Object::Error(format!("rest(): Invalid argument")))
     };
@@ -78,7 +85,7 @@ pub fn rest(objs: Vec<Object>) -> Result<Object> {
     })
 }
 
-pub fn put(objs: Vec<Object>) -> Result<Object> {
+pub fn put(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
     if objs.len() != 3 {
         return Ok(Object::Error(format!("put(): Invalid argument")))
     }
@@ -88,6 +95,7 @@ pub fn put(objs: Vec<Object>) -> Result<Object> {
     let val = &objs[2];
 
     Ok(match target {
+        Object::Hash(_) if !key.is_hashable() => Object::Error(format!("put(): Unusable as hash key: {:?}", key)),
         Object::Hash(map) => {
             let mut result = map.clone();
             result.insert(key.clone(), val.clone());
@@ -97,3 +105,240 @@ pub fn put(objs: Vec<Object>) -> Result<Object> {
         _ => Object::Error(format!("put(): Invalid argument")),
     })
 }
+
+pub fn getenv(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let Some(obj) = objs.first() else {
+        return Ok(Object::Error("getenv(): Invalid argument".to_string()))
+    };
+
+    Ok(match obj {
+        Object::String(name) => match env::var(name) {
+            Ok(val) => Object::String(val),
+            Err(_) => NULL,
+        },
+        _ => Object::Error(format!("getenv(): Invalid argument: {:?}", obj)),
+    })
+}
+
+/// Every environment variable, leaving out those which are not valid unicode.
+pub fn environment(_capabilities: &Capabilities, _objs: Vec<Object>) -> Result<Object> {
+    let vars = env::vars_os()
+        .filter_map(|(name, val)| Some((Object::String(name.into_string().ok()?), Object::String(val.into_string().ok()?))));
+
+    Ok(Object::Hash(vars.collect()))
+}
+
+pub fn exit(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let Some(obj) = objs.first() else {
+        return Ok(Object::Error("exit(): Invalid argument".to_string()))
+    };
+
+    match obj {
+        Object::Int(code @ 0..=255) => Err(Error::ExitError(*code as i32)),
+        Object::Int(code) => Ok(Object::Error(format!("exit(): Invalid exit code: {}", code))),
+        _ => Ok(Object::Error(format!("exit(): Invalid argument: {:?}", obj))),
+    }
+}
+
+pub fn json_parse(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let Some(obj) = objs.first() else {
+        return Ok(Object::Error("json_parse(): Invalid argument".to_string()))
+    };
+    let Object::String(text) = obj else {
+        return Ok(Object::Error(format!("json_parse(): Invalid argument: {:?}", obj)))
+    };
+
+    let value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(err) => return Ok(Object::Error(format!("json_parse(): {}", err))),
+    };
+
+    Ok(match from_json(value) {
+        Ok(obj) => obj,
+        Err(message) => Object::Error(format!("json_parse(): {}", message)),
+    })
+}
+
+/// JSON text of the first argument, indented when the optional second one is `true`.
+///
+/// Hash keys are written in sorted order, integer and boolean keys as their text.
+pub fn json_stringify(_capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let Some(obj) = objs.first() else {
+        return Ok(Object::Error("json_stringify(): Invalid argument".to_string()))
+    };
+    let pretty = match objs.get(1) {
+        None => false,
+        Some(Object::Bool(pretty)) => *pretty,
+        Some(other) => return Ok(Object::Error(format!("json_stringify(): Invalid argument: {:?}", other))),
+    };
+
+    let value = match to_json(obj) {
+        Ok(value) => value,
+        Err(message) => return Ok(Object::Error(format!("json_stringify(): {}", message))),
+    };
+
+    let text = if pretty { serde_json::to_string_pretty(&value) } else { serde_json::to_string(&value) };
+    Ok(Object::String(text.unwrap_or_default()))
+}
+
+fn from_json(value: Value) -> std::result::Result<Object, String> {
+    Ok(match value {
+        Value::Null => NULL,
+        Value::Bool(val) => Object::Bool(val),
+        Value::Number(number) => match number.as_i64().and_then(|val| isize::try_from(val).ok()) {
+            Some(val) => Object::Int(val),
+            None => return Err(format!("Number {} is not an integer", number)),
+        },
+        Value::String(val) => Object::String(val),
+        Value::Array(items) => Object::Array(items.into_iter().map(from_json).collect::<std::result::Result<_, _>>()?),
+        Value::Object(entries) => Object::Hash(
+            entries.into_iter()
+                .map(|(key, val)| Ok((Object::String(key), from_json(val)?)))
+                .collect::<std::result::Result<_, String>>()?,
+        ),
+    })
+}
+
+fn to_json(obj: &Object) -> std::result::Result<Value, String> {
+    Ok(match obj {
+        Object::Null => Value::Null,
+        Object::Bool(val) => Value::Bool(*val),
+        Object::Int(val) => Value::from(*val as i64),
+        Object::String(val) => Value::String(val.clone()),
+        Object::Array(items) => Value::Array(items.iter().map(to_json).collect::<std::result::Result<_, _>>()?),
+        Object::Hash(entries) => {
+            let mut map = Map::new();
+            for (key, val) in entries {
+                let key = match key {
+                    Object::String(key) => key.clone(),
+                    Object::Int(key) => key.to_string(),
+                    Object::Bool(key) => key.to_string(),
+                    _ => return Err(format!("Can not use {:?} as a key", key)),
+                };
+                // `1` and `"1"` both become the key "1", keeping either would drop the other.
+                if map.contains_key(&key) {
+                    return Err(format!("Duplicate key {:?}", key));
+                }
+                map.insert(key, to_json(val)?);
+            }
+            Value::Object(map)
+        }
+        Object::Function { name, .. } | Object::BuiltIn { name, .. } => return Err(format!("Can not serialize function {}", name)),
+        Object::Error(message) => return Err(format!("Can not serialize error {:?}", message)),
+        Object::Return(val) => to_json(val)?,
+    })
+}
+
+/// Path given as the first argument of the file system builtin `name`, checked against `capabilities`.
+///
+/// The inner error is the message the builtin returns as an error value.
+fn fs_path(capabilities: &Capabilities, name: &str, objs: &[Object]) -> Result<std::result::Result<PathBuf, String>> {
+    let Some(Object::String(path)) = objs.first() else {
+        return Ok(Err(format!("{}(): Invalid argument: {:?}", name, objs.first())));
+    };
+
+    Ok(capabilities.fs_path(name, path)?.map_err(|err| format!("{}(): {}", name, err)))
+}
+
+/// Content given as the second argument of the file system builtin `name`.
+fn fs_content<'a>(name: &str, objs: &'a [Object]) -> std::result::Result<&'a str, String> {
+    match objs.get(1) {
+        Some(Object::String(content)) => Ok(content),
+        other => Err(format!("{}(): Invalid argument: {:?}", name, other)),
+    }
+}
+
+/// Value of the file system builtin `name`, turning the error of `result` into an error value.
+fn fs_result(name: &str, result: io::Result<Object>) -> Object {
+    result.unwrap_or_else(|err| Object::Error(format!("{}(): {}", name, err)))
+}
+
+pub fn read_file(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let path = match fs_path(capabilities, "read_file", &objs)? {
+        Ok(path) => path,
+        Err(message) => return Ok(Object::Error(message)),
+    };
+
+    Ok(fs_result("read_file", fs::read_to_string(path).map(Object::String)))
+}
+
+pub fn write_file(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let (path, content) = match (fs_path(capabilities, "write_file", &objs)?, fs_content("write_file", &objs)) {
+        (Ok(path), Ok(content)) => (path, content),
+        (Err(message), _) | (_, Err(message)) => return Ok(Object::Error(message)),
+    };
+
+    Ok(fs_result("write_file", fs::write(path, content).map(|_| NULL)))
+}
+
+pub fn append_file(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let (path, content) = match (fs_path(capabilities, "append_file", &objs)?, fs_content("append_file", &objs)) {
+        (Ok(path), Ok(content)) => (path, content),
+        (Err(message), _) | (_, Err(message)) => return Ok(Object::Error(message)),
+    };
+
+    let result = fs::OpenOptions::new().append(true).create(true).open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()));
+    Ok(fs_result("append_file", result.map(|_| NULL)))
+}
+
+/// Names of the entries of a directory, sorted.
+pub fn list_dir(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let path = match fs_path(capabilities, "list_dir", &objs)? {
+        Ok(path) => path,
+        Err(message) => return Ok(Object::Error(message)),
+    };
+
+    let names = fs::read_dir(path).and_then(|entries| {
+        let mut names = entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()?;
+        names.sort();
+        Ok(Object::Array(names.into_iter().map(Object::String).collect()))
+    });
+    Ok(fs_result("list_dir", names))
+}
+
+pub fn exists(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    match fs_path(capabilities, "exists", &objs)? {
+        Ok(path) => Ok(Object::Bool(path.exists())),
+        // Paths within the roots whose directory is missing do not exist either.
+        Err(_) if matches!(objs.first(), Some(Object::String(_))) => Ok(Object::Bool(false)),
+        Err(message) => Ok(Object::Error(message)),
+    }
+}
+
+pub fn remove_file(capabilities: &Capabilities, objs: Vec<Object>) -> Result<Object> {
+    let path = match fs_path(capabilities, "remove_file", &objs)? {
+        Ok(path) => path,
+        Err(message) => return Ok(Object::Error(message)),
+    };
+
+    Ok(fs_result("remove_file", fs::remove_file(path).map(|_| NULL)))
+}
+
+/// The rest of stdin.
+pub fn input(_capabilities: &Capabilities, _objs: Vec<Object>) -> Result<Object> {
+    let mut text = String::new();
+    Ok(match io::stdin().read_to_string(&mut text) {
+        Ok(_) => Object::String(text),
+        Err(err) => Object::Error(format!("input(): {}", err)),
+    })
+}
+
+/// Next line of stdin without its line ending, null once stdin is exhausted.
+pub fn read_line(_capabilities: &Capabilities, _objs: Vec<Object>) -> Result<Object> {
+    Ok(match io::stdin().lock().lines().next() {
+        Some(Ok(line)) => Object::String(line),
+        Some(Err(err)) => Object::Error(format!("read_line(): {}", err)),
+        None => NULL,
+    })
+}
+
+/// The remaining lines of stdin without their line endings.
+pub fn read_lines(_capabilities: &Capabilities, _objs: Vec<Object>) -> Result<Object> {
+    Ok(match io::stdin().lock().lines().collect::<io::Result<Vec<_>>>() {
+        Ok(lines) => Object::Array(lines.into_iter().map(Object::String).collect()),
+        Err(err) => Object::Error(format!("read_lines(): {}", err)),
+    })
+}
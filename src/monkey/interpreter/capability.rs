@@ -0,0 +1,113 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::monkey::error::Error;
+use crate::monkey::Result;
+
+/// What a script may reach outside of the interpreter, nothing unless granted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    /// Directories the file system builtins may access, along with everything below them.
+    fs_roots: Vec<PathBuf>,
+}
+
+impl Capabilities {
+    /// Allow the file system builtins to access `root` and everything below it.
+    pub fn with_fs_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.fs_roots.push(root.into());
+        self
+    }
+
+    /// Resolve `path` for the builtin `name`, failing unless it lies within one of the roots.
+    ///
+    /// The outer error denies the access, the inner one is what resolving the path ran into.
+    pub fn fs_path(&self, name: &str, path: &str) -> Result<io::Result<PathBuf>> {
+        if self.fs_roots.is_empty() {
+            return Err(Error::PermissionDeniedError(format!("{}() needs file system access", name)));
+        }
+
+        // Paths which do not resolve are only reported as missing within the roots.
+        let resolved = resolve(Path::new(path));
+        let checked = match &resolved {
+            Ok(resolved) => resolved.clone(),
+            Err(_) => std::env::current_dir().unwrap_or_default().join(path),
+        };
+
+        // Roots which do not exist allow nothing.
+        let allowed = self.fs_roots.iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| checked.starts_with(root));
+        if !allowed {
+            return Err(Error::PermissionDeniedError(format!("{}() can not access {:?}", name, path)));
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// `path` with symbolic links and `..` resolved, the last component may not exist yet.
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    if let Ok(resolved) = path.canonicalize() {
+        return Ok(resolved);
+    }
+
+    let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No such file or directory"));
+    };
+    let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+
+    Ok(parent.canonicalize()?.join(file_name))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::process;
+
+    use crate::monkey::interpreter::Evaluate;
+    use crate::monkey::interpreter::object::Object;
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    fn evaluate(capabilities: Capabilities, source: &str) -> Result<Object> {
+        let lexer = MonkeyLexer::new(source);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        Evaluate::new().with_capabilities(capabilities).evaluate(&program)
+    }
+
+    #[test]
+    fn test_fs_builtins() {
+        let root = std::env::temp_dir().join(format!("monkey-fs-{}", process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let capabilities = Capabilities::default().with_fs_root(&root);
+
+        let source = format!(r#"
+let dir = "{}";
+write_file(dir + "/a.txt", "one");
+append_file(dir + "/a.txt", " two");
+let text = read_file(dir + "/sub/../a.txt");
+let listed = list_dir(dir);
+remove_file(dir + "/a.txt");
+[text, listed, exists(dir + "/a.txt"), exists(dir + "/sub")]
+"#, root.display());
+        let result = evaluate(capabilities.clone(), &source);
+        assert_eq!(result.unwrap().to_string(), "[ \"one two\", [ \"a.txt\", \"sub\" ], false, true ]");
+
+        let result = evaluate(capabilities.clone(), &format!("read_file(\"{}/missing.txt\")", root.display()));
+        assert_eq!(result.unwrap().to_string(), "Error: read_file(): No such file or directory (os error 2)");
+
+        // Relative paths, `..` and roots which are prefixes of the path as text do not escape.
+        let outside = format!("{}-other/x.txt", root.display());
+        for path in ["Cargo.toml", &format!("{}/../x.txt", root.display()), &outside] {
+            let result = evaluate(capabilities.clone(), &format!("read_file(\"{}\")", path));
+            assert!(matches!(result, Err(Error::PermissionDeniedError(_))), "{}: {:?}", path, result);
+        }
+
+        let result = evaluate(Capabilities::default(), "exists(\"Cargo.toml\")");
+        assert_eq!(result, Err(Error::PermissionDeniedError("exists() needs file system access".to_string())));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
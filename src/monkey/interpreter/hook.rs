@@ -0,0 +1,34 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::monkey::interpreter::{Environment, Evaluate};
+use crate::monkey::interpreter::object::Object;
+use crate::monkey::parser::ast::StatementNode;
+use crate::monkey::Result;
+use crate::monkey::token::Span;
+
+/// Observes evaluation, used by the debugger to pause a running program and by the profiler.
+pub trait Hook {
+    /// Called before `statement` written at `span` runs in `env`.
+    ///
+    /// Returning an error stops the evaluation with it.
+    fn before_statement(&self, _eval: &Evaluate, _statement: &StatementNode, _span: &Span, _env: &Rc<RefCell<Environment>>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when a function or builtin starts running, including each call a tail call
+    /// replaces the current one with.
+    fn enter_call(&self, _function: &Object) {}
+
+    /// Called when the function of the matching `enter_call` is done.
+    fn exit_call(&self) {}
+
+    /// Called when the running code creates strings, arrays or hashes taking `bytes`.
+    fn allocate(&self, _bytes: usize) {}
+
+    /// Called when an `if` picks its consequence, `taken`, or its alternative.
+    ///
+    /// The `if` is the `index`th one - counting from 0 in source order - the statement at
+    /// `statement` reaches, leaving out those inside nested blocks and functions.
+    fn branch(&self, _statement: &Span, _index: usize, _taken: bool) {}
+}
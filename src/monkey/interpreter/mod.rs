@@ -1,17 +1,33 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ptr::hash;
 use std::rc::Rc;
-use crate::monkey::interpreter::builtin::{first, last, push, put, rest, str_len};
+use std::time::Instant;
+use crate::monkey::interpreter::hook::Hook;
+use crate::monkey::interpreter::builtin::{append_file, environment, exists, exit, first, getenv, input, json_parse, json_stringify, last, list_dir, push, put, read_file, read_line, read_lines, remove_file, rest, str_len, write_file};
+use crate::monkey::interpreter::capability::Capabilities;
 use crate::monkey::interpreter::object::{FALSE, NULL, Object, TRUE};
 use crate::monkey::parser::ast::{ExpressionNode, Identifier, Operator, Program, StatementNode};
+use crate::monkey::error::{Budget, Error};
 use crate::monkey::Result;
+use crate::monkey::token::Span;
 
 pub mod builtin;
-mod object;
-
+pub mod capability;
+pub mod hook;
+pub mod object;
+
+/// Value of `$value`, returning it from the enclosing function instead when it is an error.
+macro_rules! propagate {
+    ($value: expr) => {
+        match $value {
+            error @ Object::Error(_) => return Ok(error),
+            value => value,
+        }
+    };
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Environment {
@@ -24,33 +40,125 @@ impl Environment {
         store.insert("len".to_string(), Object::BuiltIn {
             name: "len".to_string(),
             params: 1,
+            optional: 0,
             program: str_len,
         });
         store.insert("first".to_string(), Object::BuiltIn {
             name: "first".to_string(),
             params: 1,
+            optional: 0,
             program: first,
         });
         store.insert("last".to_string(), Object::BuiltIn {
             name: "last".to_string(),
             params: 1,
+            optional: 0,
             program: last,
         });
         store.insert("push".to_string(), Object::BuiltIn {
             name: "push".to_string(),
             params: 2,
+            optional: 0,
             program: push,
         });
         store.insert("rest".to_string(), Object::BuiltIn {
             name: "rest".to_string(),
             params: 1,
+            optional: 0,
             program: rest,
         });
         store.insert("put". to_string(), Object::BuiltIn {
             name: "put".to_string(),
             params: 3,
+            optional: 0,
             program: put,
         });
+        store.insert("getenv".to_string(), Object::BuiltIn {
+            name: "getenv".to_string(),
+            params: 1,
+            optional: 0,
+            program: getenv,
+        });
+        store.insert("env".to_string(), Object::BuiltIn {
+            name: "env".to_string(),
+            params: 0,
+            optional: 0,
+            program: environment,
+        });
+        store.insert("exit".to_string(), Object::BuiltIn {
+            name: "exit".to_string(),
+            params: 1,
+            optional: 0,
+            program: exit,
+        });
+        store.insert("json_parse".to_string(), Object::BuiltIn {
+            name: "json_parse".to_string(),
+            params: 1,
+            optional: 0,
+            program: json_parse,
+        });
+        store.insert("json_stringify".to_string(), Object::BuiltIn {
+            name: "json_stringify".to_string(),
+            params: 2,
+            optional: 1,
+            program: json_stringify,
+        });
+        store.insert("read_file".to_string(), Object::BuiltIn {
+            name: "read_file".to_string(),
+            params: 1,
+            optional: 0,
+            program: read_file,
+        });
+        store.insert("write_file".to_string(), Object::BuiltIn {
+            name: "write_file".to_string(),
+            params: 2,
+            optional: 0,
+            program: write_file,
+        });
+        store.insert("append_file".to_string(), Object::BuiltIn {
+            name: "append_file".to_string(),
+            params: 2,
+            optional: 0,
+            program: append_file,
+        });
+        store.insert("list_dir".to_string(), Object::BuiltIn {
+            name: "list_dir".to_string(),
+            params: 1,
+            optional: 0,
+            program: list_dir,
+        });
+        store.insert("exists".to_string(), Object::BuiltIn {
+            name: "exists".to_string(),
+            params: 1,
+            optional: 0,
+            program: exists,
+        });
+        store.insert("remove_file".to_string(), Object::BuiltIn {
+            name: "remove_file".to_string(),
+            params: 1,
+            optional: 0,
+            program: remove_file,
+        });
+        store.insert("input".to_string(), Object::BuiltIn {
+            name: "input".to_string(),
+            params: 0,
+            optional: 0,
+            program: input,
+        });
+        store.insert("read_line".to_string(), Object::BuiltIn {
+            name: "read_line".to_string(),
+            params: 0,
+            optional: 0,
+            program: read_line,
+        });
+        store.insert("read_lines".to_string(), Object::BuiltIn {
+            name: "read_lines".to_string(),
+            params: 0,
+            optional: 0,
+            program: read_lines,
+        });
+        // Replaced by the arguments given with `Evaluate::with_args`.
+        store.insert("args".to_string(), Object::Array(vec![]));
 
         Environment {
             store,
@@ -60,6 +168,10 @@ impl Environment {
         self.store.insert(name.to_string(), value.clone());
     }
 
+    pub fn bindings(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.store.iter()
+    }
+
     pub fn get(&self, name: &str) -> Option<Object> {
         match self.store.get(name) {
             None => None,
@@ -68,18 +180,208 @@ impl Environment {
     }
 }
 
-pub struct Evaluate {}
+/// Calls nested deeper than this fail with a `StackOverflowError`.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Number of frames a `StackOverflowError` lists.
+const OVERFLOW_FRAMES: usize = 10;
+
+/// Stack left before a call moves to a fresh segment, more than a single call level takes.
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+pub struct Evaluate {
+    max_depth: usize,
+    /// Every active call, innermost last.
+    frames: RefCell<Vec<Frame>>,
+    /// Fuel given with `with_fuel` along with what is left of it.
+    fuel: Option<(u64, Cell<u64>)>,
+    deadline: Option<Instant>,
+    max_heap: Option<usize>,
+    /// Estimated heap of the values alive in the current run, see `Object::heap_size`.
+    heap: Cell<usize>,
+    peak_heap: Cell<usize>,
+    hook: Option<Rc<dyn Hook>>,
+    /// Span of the statement running, locating `if`s.
+    statement: RefCell<Option<Span>>,
+    /// Number of `if`s the running statement reached so far, telling them apart.
+    branches: Cell<usize>,
+    /// Command line arguments of the script, bound to `args`.
+    args: Vec<String>,
+    capabilities: Capabilities,
+}
+
+/// Where statements are evaluated, deciding which calls may reuse the current frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Position {
+    /// Outside of any function.
+    Script,
+    /// Inside a function body - a `return` ends the call.
+    Body,
+    /// Inside a function body where the value of the block is the value of the call.
+    Tail,
+}
+
+/// Outcome of evaluating a function body.
+enum Flow {
+    Value(Object),
+    /// Call to make in place of the current one - its frame, function and arguments.
+    TailCall(Frame, Object, Vec<Object>),
+}
+
+/// An active call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    /// Line the call was made on, starting from 1 - `None` for functions not called by name.
+    pub line: Option<usize>,
+}
+
+impl Frame {
+    /// Frame for a call of `function`.
+    fn of(function: &ExpressionNode) -> Self {
+        match function {
+            ExpressionNode::Identifier(ident) => Frame { name: ident.0.clone(), line: Some(ident.1.line_start + 1) },
+            _ => Frame { name: "<anonymous>".to_string(), line: None },
+        }
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "at {} (line {})", self.name, line),
+            None => write!(f, "at {}", self.name),
+        }
+    }
+}
 
 impl Evaluate {
     pub fn new() -> Self {
-        Evaluate {}
+        Evaluate {
+            max_depth: DEFAULT_MAX_DEPTH,
+            frames: RefCell::new(Vec::new()),
+            fuel: None,
+            deadline: None,
+            max_heap: None,
+            heap: Cell::new(0),
+            peak_heap: Cell::new(0),
+            hook: None,
+            statement: RefCell::new(None),
+            branches: Cell::new(0),
+            args: vec![],
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Allow `fuel` statements and expressions to be evaluated, shared by all later evaluations.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some((fuel, Cell::new(fuel)));
+        self
+    }
+
+    /// Stop evaluating with an error once `deadline` has passed.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Fail a run once the strings, arrays and hashes it holds take more than `bytes`.
+    pub fn with_max_heap(mut self, bytes: usize) -> Self {
+        self.max_heap = Some(bytes);
+        self
+    }
+
+    /// Most heap held at once during the last run.
+    pub fn peak_heap(&self) -> usize {
+        self.peak_heap.get()
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Grant the builtins `capabilities`, without them scripts can not touch the file system.
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_hook(mut self, hook: Rc<dyn Hook>) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// Active calls, innermost first.
+    pub fn call_stack(&self) -> Vec<Frame> {
+        self.frames.borrow().iter().rev().cloned().collect()
+    }
+
+    pub fn fuel_remaining(&self) -> Option<u64> {
+        self.fuel.as_ref().map(|(_, left)| left.get())
+    }
+
+    /// Charge one unit of work against the budgets.
+    fn tick(&self) -> Result<()> {
+        if let Some((fuel, left)) = &self.fuel {
+            if left.get() == 0 {
+                return Err(Error::BudgetExhaustedError(Budget::Fuel(*fuel)));
+            }
+            left.set(left.get() - 1);
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Error::BudgetExhaustedError(Budget::Deadline)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Charge `bytes` of new strings, arrays and hashes against the heap ceiling.
+    fn allocate(&self, bytes: usize) -> Result<()> {
+        if let (Some(hook), true) = (&self.hook, bytes > 0) {
+            hook.allocate(bytes);
+        }
+
+        let heap = self.heap.get() + bytes;
+        self.heap.set(heap);
+        self.peak_heap.set(self.peak_heap.get().max(heap));
+
+        match self.max_heap {
+            Some(max_heap) if heap > max_heap => Err(Error::BudgetExhaustedError(Budget::Heap(max_heap))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drop what was allocated since the heap was `base`, except for `value` which lives on.
+    fn release(&self, base: usize, value: Object) -> Object {
+        self.heap.set(base + value.heap_size());
+        value
     }
 
     pub fn evaluate(&self, program: &Program) -> Result<Object> {
-        let env = Rc::new(RefCell::new(Environment::new()));
+        self.heap.set(0);
+        self.peak_heap.set(0);
+
+        let env = self.global_environment()?;
         self.evaluate_program(program, &env)
     }
 
+    /// Environment programs start out in, holding the builtins and `args`.
+    pub fn global_environment(&self) -> Result<Rc<RefCell<Environment>>> {
+        let args = Object::Array(self.args.iter().map(|arg| Object::String(arg.clone())).collect());
+        self.allocate(args.heap_size())?;
+
+        let env = Rc::new(RefCell::new(Environment::new()));
+        env.borrow_mut().store("args", &args);
+        Ok(env)
+    }
+
     pub fn evaluate_program(&self, program: &Program, env: &Rc<RefCell<Environment>>) -> Result<Object> {
         let result = self.evaluate_block(program, env)?;
         Ok(match result {
@@ -89,47 +391,151 @@ impl Evaluate {
     }
 
     pub fn evaluate_block(&self, program: &Program, env: &Rc<RefCell<Environment>>) -> Result<Object> {
+        match self.evaluate_statements(program, env, Position::Script)? {
+            Flow::Value(result) => Ok(result),
+            Flow::TailCall(..) => unreachable!("tail calls only happen within functions"),
+        }
+    }
+
+    fn evaluate_statements(&self, program: &Program, env: &Rc<RefCell<Environment>>, position: Position) -> Result<Flow> {
         let mut result = NULL;
 
-        for statement in &program.statements {
-            result = match statement {
-                StatementNode::Let(ident, expr) => self.eval_let_statement(ident, expr, env)?,
-                StatementNode::If { condition, consequence, alternative } => self.eval_if_statement(condition, consequence, alternative, env)?,
-                StatementNode::Return(expression) => Object::Return(Box::new(self.expression(expression, env)?)),
-                StatementNode::Expression { expression } => self.expression(expression, env)?,
+        let count = program.statements.len();
+        for (idx, statement) in program.statements.iter().enumerate() {
+            let tail = position == Position::Tail && idx + 1 == count;
+            self.tick()?;
+            let base = self.heap.get();
+
+            let outer = match program.span(idx) {
+                Some(span) => {
+                    if let Some(hook) = &self.hook {
+                        hook.before_statement(self, statement, span, env)?;
+                    }
+                    Some((self.statement.replace(Some(span.clone())), self.branches.replace(0)))
+                }
+                None => None,
+            };
+
+            let flow = match statement {
+                StatementNode::Let(ident, expr) => Flow::Value(self.eval_let_statement(ident, expr, env)?),
+                StatementNode::If { condition, consequence, alternative } => {
+                    let position = match position {
+                        Position::Script => Position::Script,
+                        _ if tail => Position::Tail,
+                        _ => Position::Body,
+                    };
+                    self.eval_if_flow(condition, consequence, alternative, env, position)?
+                }
+                StatementNode::Return(expression) if position != Position::Script => {
+                    match self.tail_expression(expression, env)? {
+                        Flow::Value(result) => Flow::Value(Object::Return(Box::new(result))),
+                        call => call,
+                    }
+                }
+                StatementNode::Return(expression) => Flow::Value(Object::Return(Box::new(self.expression(expression, env)?))),
+                StatementNode::Expression { expression } if tail => self.tail_expression(expression, env)?,
+                StatementNode::Expression { expression } => Flow::Value(self.expression(expression, env)?),
             };
 
-            if let Object::Return(_) = result {
+            if let Some((statement, branches)) = outer {
+                self.statement.replace(statement);
+                self.branches.set(branches);
+            }
+
+            match flow {
+                Flow::Value(value) => result = value,
+                call => return Ok(call),
+            }
+
+            // What an expression statement made is gone once it ran, unless it is the value of the
+            // block. Bindings of `let`s and of the blocks of `if`s live on.
+            if let StatementNode::Expression { expression } = statement {
+                if !matches!(**expression, ExpressionNode::If { .. }) {
+                    let kept = if idx + 1 == count { result.heap_size() } else { 0 };
+                    self.heap.set(base + kept);
+                }
+            }
+
+            // Errors end every block up to where the script stops.
+            if let Object::Return(_) | Object::Error(_) = result {
                 break;
             }
         };
 
-        Ok(result)
+        Ok(Flow::Value(result))
+    }
+
+    /// Evaluate an expression whose value is the value of the function, handing a call back to
+    /// the trampoline in `call` instead of making it.
+    fn tail_expression(&self, expression: &ExpressionNode, env: &Rc<RefCell<Environment>>) -> Result<Flow> {
+        match expression {
+            ExpressionNode::Call { function: function_node, params } => {
+                self.tick()?;
+                let function = match self.expression(function_node, env)? {
+                    error @ Object::Error(_) => return Ok(Flow::Value(error)),
+                    function => function,
+                };
+                let args = match self.arguments(params, env)? {
+                    Ok(args) => args,
+                    Err(error) => return Ok(Flow::Value(error)),
+                };
+
+                let frame = Frame::of(function_node);
+                if matches!(function, Object::Function { .. }) {
+                    Ok(Flow::TailCall(frame, function, args))
+                } else {
+                    Ok(Flow::Value(self.call(frame, function, args, env)?))
+                }
+            }
+            ExpressionNode::If { condition, consequence, alternative } => {
+                self.tick()?;
+                self.eval_if_flow(condition, consequence, alternative, env, Position::Tail)
+            }
+            _ => Ok(Flow::Value(self.expression(expression, env)?)),
+        }
     }
 
     pub fn eval_let_statement(&self, identifier: &Identifier, expression: &ExpressionNode, env: &Rc<RefCell<Environment>>) -> Result<Object> {
-        let result = self.expression(expression, env)?;
+        let mut result = self.expression(expression, env)?;
         if matches!(result, Object::Error(_)) {
             return Ok(result);
         }
 
+        if let (ExpressionNode::Function { .. }, Object::Function { name, .. }) = (expression, &mut result) {
+            *name = identifier.0.clone();
+        }
+
         env.borrow_mut().store(&identifier.0, &result);
 
         Ok(result)
     }
 
-    pub fn eval_if_statement(&self, condition: &ExpressionNode, consequence: &Program, alternative: &Option<Box<Program>>, env: &Rc<RefCell<Environment>>) -> Result<Object> {
-        let result = self.expression(condition, env)?;
+    pub fn eval_if(&self, condition: &ExpressionNode, consequence: &Program, alternative: &Option<Box<Program>>, env: &Rc<RefCell<Environment>>) -> Result<Object> {
+        match self.eval_if_flow(condition, consequence, alternative, env, Position::Script)? {
+            Flow::Value(result) => Ok(result),
+            Flow::TailCall(..) => unreachable!("tail calls only happen within functions"),
+        }
+    }
 
-        Ok(
-            if self.is_truthy(&result) {
-                self.evaluate_block(&consequence, env)?
-            } else if let Some(program) = alternative {
-                self.evaluate_block(&program, env)?
-            } else {
-                NULL
-            }
-        )
+    fn eval_if_flow(&self, condition: &ExpressionNode, consequence: &Program, alternative: &Option<Box<Program>>, env: &Rc<RefCell<Environment>>, position: Position) -> Result<Flow> {
+        let index = self.branches.replace(self.branches.get() + 1);
+        let result = match self.expression(condition, env)? {
+            error @ Object::Error(_) => return Ok(Flow::Value(error)),
+            result => result,
+        };
+        let taken = self.is_truthy(&result);
+
+        if let (Some(hook), Some(statement)) = (&self.hook, &*self.statement.borrow()) {
+            hook.branch(statement, index, taken);
+        }
+
+        if taken {
+            self.evaluate_statements(consequence, env, position)
+        } else if let Some(program) = alternative {
+            self.evaluate_statements(program, env, position)
+        } else {
+            Ok(Flow::Value(NULL))
+        }
     }
 
     pub fn is_truthy(&self, condition: &Object) -> bool {
@@ -142,7 +548,9 @@ impl Evaluate {
     }
 
     pub fn expression(&self, expression: &ExpressionNode, env: &Rc<RefCell<Environment>>) -> Result<Object> {
-        Ok(match expression {
+        self.tick()?;
+
+        let result = match expression {
             ExpressionNode::Int(val) => Object::Int(*val),
             ExpressionNode::Bool(val) => match val {
                 true => TRUE,
@@ -153,67 +561,45 @@ impl Evaluate {
                 None => Object::Error(format!("identifier not found: {:?}", ident))
             },
             ExpressionNode::String(str) => Object::String(str.clone()),
+            ExpressionNode::InterpolatedString { parts } => self.eval_interpolated_string(parts, env)?,
             ExpressionNode::Prefix { operator, expression } => self.eval_prefix(operator, expression, env)?,
             ExpressionNode::Infix { operator, left, right } => {
-                let left = self.expression(left, env)?;
-                let right = self.expression(right, env)?;
+                let left = propagate!(self.expression(left, env)?);
+                let right = propagate!(self.expression(right, env)?);
                 self.eval_infix(operator, &left, &right)?
             }
-            ExpressionNode::Function { params, body } => Object::Function {
+            ExpressionNode::Function { params, body, span } => Object::Function {
+                name: match &span.0 {
+                    Some(span) => format!("<fn {}:{}>", span.line_start + 1, span.start + 1),
+                    None => "<anonymous>".to_string(),
+                },
                 ident: params.clone(),
                 program: body.clone(),
                 env: Rc::clone(env),
             },
-            ExpressionNode::Call { function, params } => {
-                let func = self.expression(function, env)?;
-
-                if let Object::Function { ident, program, env: cap } = func {
-                    let mut new_env = Environment::new();
-                    for (name, obj) in &env.borrow().store {
-                        new_env.store(name, obj);
-                    }
-
-                    for (_, (ident, item)) in ident.iter().zip(params).enumerate() {
-                        let result = self.expression(item, env)?;
-                        new_env.store(&ident.0, &result)
-                    }
-
-                    self.evaluate_program(&program, &Rc::new(RefCell::new(new_env)))?
-                } else if let Object::BuiltIn { name, params: params_size, program } = func {
-                    let mut func_params: Vec<Object> = Vec::new();
-                    for item in params {
-                        func_params.push(self.expression(item, env)?)
-                    }
-
-                    if func_params.len() != params_size {
-                        return Ok(Object::Error(format!("{}(): Invalid number of argument - expected {} got {}", name, params_size, func_params.len())));
-                    }
-
-                    program(func_params)?
-                } else if let Object::Error(message) = func {
-                    panic!("{}", message)
-                } else {
-                    panic!()
-                }
-            }
-            ExpressionNode::ArrayLiteral { params } => {
-                let mut func_params: Vec<Object> = Vec::new();
-                for item in params {
-                    func_params.push(self.expression(item, env)?)
-                }
-
-                Object::Array(func_params)
+            ExpressionNode::Call { function: function_node, params } => {
+                let function = propagate!(self.expression(function_node, env)?);
+                let args = match self.arguments(params, env)? {
+                    Ok(args) => args,
+                    Err(error) => return Ok(error),
+                };
+
+                self.call(Frame::of(function_node), function, args, env)?
             }
+            ExpressionNode::ArrayLiteral { params } => match self.arguments(params, env)? {
+                Ok(items) => Object::Array(items),
+                Err(error) => return Ok(error),
+            },
             ExpressionNode::Index { left, index } => {
-                let left_obj = self.expression(left, env)?;
+                let left_obj = propagate!(self.expression(left, env)?);
                 match left_obj {
                     Object::Array(vec) => {
-                        let index_obj = self.expression(index, env)?;
+                        let index_obj = propagate!(self.expression(index, env)?);
                         let Object::Int(offset) = &index_obj else {
                             return Ok(Object::Error(format!("Invalid index value: {:?}", index_obj)));
                         };
 
-                        if offset.is_negative() || vec.len() < *offset as usize {
+                        if offset.is_negative() || vec.len() <= *offset as usize {
                             return Ok(NULL);
                         }
 
@@ -221,47 +607,167 @@ impl Evaluate {
                         vec[*offset as usize].clone()
                     }
                     Object::Hash(map) => {
-                        let index_obj = self.expression(index, env)?;
-                        map[&index_obj].clone()
+                        let index_obj = propagate!(self.expression(index, env)?);
+                        if !index_obj.is_hashable() {
+                            return Ok(Object::Error(format!("Unusable as hash key: {:?}", index_obj)));
+                        }
 
+                        map.get(&index_obj).cloned().unwrap_or(NULL)
                     },
                     _ => return Ok(Object::Error(format!("Can not index object type: {:?}", left_obj))),
                 }
 
             }
+            ExpressionNode::If { condition, consequence, alternative } => self.eval_if(condition, consequence, alternative, env)?,
             ExpressionNode::HashLiteral { params} => {
                 let mut map: HashMap<Object, Object> = HashMap::new();
 
                 for (key, val) in params {
-                    let key_result = self.expression(key, env)?;
-                    let val_result = self.expression(val, env)?;
+                    let key_result = propagate!(self.expression(key, env)?);
+                    if !key_result.is_hashable() {
+                        return Ok(Object::Error(format!("Unusable as hash key: {:?}", key_result)));
+                    }
+                    let val_result = propagate!(self.expression(val, env)?);
 
                     map.insert(key_result, val_result);
                 }
 
                 Object::Hash(map)
             }
-            _ => todo!()
-        })
+        };
+
+        // Only new values count - literals take ownership of their items, and reading a value
+        // shares what is already counted. Builtins charge for their results in `run_call`.
+        self.allocate(match expression {
+            ExpressionNode::ArrayLiteral { .. } | ExpressionNode::HashLiteral { .. } => result.shallow_heap_size(),
+            ExpressionNode::String(_) | ExpressionNode::InterpolatedString { .. } | ExpressionNode::Infix { .. } => result.heap_size(),
+            _ => 0,
+        })?;
+
+        Ok(result)
+    }
+
+    /// Values of `params`, or the first of them which is an error.
+    fn arguments(&self, params: &[Box<ExpressionNode>], env: &Rc<RefCell<Environment>>) -> Result<std::result::Result<Vec<Object>, Object>> {
+        let mut args: Vec<Object> = Vec::new();
+        for item in params {
+            match self.expression(item, env)? {
+                error @ Object::Error(_) => return Ok(Err(error)),
+                arg => args.push(arg),
+            }
+        }
+
+        Ok(Ok(args))
+    }
+
+    /// Call `function` from `env`, `frame` describing the call.
+    ///
+    /// Calls in tail position come back here as a `Flow::TailCall` and run in this loop instead
+    /// of recursing, so tail recursive functions run in constant stack space.
+    pub fn call(&self, frame: Frame, function: Object, args: Vec<Object>, env: &Rc<RefCell<Environment>>) -> Result<Object> {
+        let depth = {
+            let mut frames = self.frames.borrow_mut();
+            frames.push(frame);
+            frames.len()
+        };
+
+        let result = if depth > self.max_depth {
+            let all = self.frames.borrow();
+            let mut frames: Vec<String> = all.iter().rev().take(OVERFLOW_FRAMES).map(Frame::to_string).collect();
+            if all.len() > OVERFLOW_FRAMES {
+                frames.push(format!("... {} more", all.len() - OVERFLOW_FRAMES));
+            }
+            Err(Error::StackOverflowError { max_depth: self.max_depth, frames })
+        } else {
+            // The interpreter recurses on the Rust stack, grow it instead of crashing the host.
+            let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || self.run_call(function, args, env));
+            if let Some(hook) = &self.hook {
+                hook.exit_call();
+            }
+            result
+        };
+
+        self.frames.borrow_mut().pop();
+        result
+    }
+
+    fn run_call(&self, function: Object, args: Vec<Object>, env: &Rc<RefCell<Environment>>) -> Result<Object> {
+        let (mut function, mut args) = (function, args);
+        let base = self.heap.get();
+        // Environment of the function running, sharing the values of the caller's.
+        let mut frame: Option<Rc<RefCell<Environment>>> = None;
+
+        loop {
+            if let Some(hook) = &self.hook {
+                hook.enter_call(&function);
+            }
+
+            match function {
+                Object::Function { ident, program, .. } => {
+                    // Tail calls rebind the parameters in the environment of the call they replace.
+                    let frame = frame.get_or_insert_with(|| Rc::new(RefCell::new(env.borrow().clone())));
+                    for (ident, item) in ident.iter().zip(args) {
+                        frame.borrow_mut().store(&ident.0, &item)
+                    }
+
+                    match self.evaluate_statements(&program, frame, Position::Tail)? {
+                        Flow::Value(Object::Return(val)) => return Ok(self.release(base, *val)),
+                        Flow::Value(val) => return Ok(self.release(base, val)),
+                        Flow::TailCall(name, next, next_args) => {
+                            *self.frames.borrow_mut().last_mut().unwrap() = name;
+                            self.heap.set(base + next_args.iter().map(Object::heap_size).sum::<usize>());
+                            if let Some(hook) = &self.hook {
+                                hook.exit_call();
+                            }
+                            (function, args) = (next, next_args);
+                        }
+                    }
+                }
+                Object::BuiltIn { name, params: params_size, optional, program } => {
+                    if args.len() > params_size || args.len() < params_size - optional {
+                        return Ok(Object::Error(format!("{}(): Invalid number of argument - expected {} got {}", name, params_size, args.len())));
+                    }
+
+                    let result = program(&self.capabilities, args)?;
+                    self.allocate(result.heap_size())?;
+                    return Ok(result);
+                }
+                error @ Object::Error(_) => return Ok(error),
+                other => return Ok(Object::Error(format!("Not a function: {:?}", other))),
+            }
+        }
+    }
+
+    fn eval_interpolated_string(&self, parts: &[ExpressionNode], env: &Rc<RefCell<Environment>>) -> Result<Object> {
+        let mut result = String::new();
+
+        for part in parts {
+            match self.expression(part, env)? {
+                Object::String(str) => result.push_str(&str),
+                error @ Object::Error(_) => return Ok(error),
+                val => result.push_str(&val.to_string()),
+            }
+        }
+
+        Ok(Object::String(result))
     }
 
     fn eval_prefix(&self, operator: &Operator, expression: &Box<ExpressionNode>, env: &Rc<RefCell<Environment>>) -> Result<Object> {
         Ok(match operator {
-            Operator::Not => match self.expression(expression, env) {
-                Ok(result) => match result {
-                    Object::Bool(true) => FALSE,
-                    Object::Bool(false) => TRUE,
-                    Object::Int(0) => TRUE,
-                    _ => FALSE,
-                },
-                Err(_) => panic!()
+            Operator::Not => match self.expression(expression, env)? {
+                error @ Object::Error(_) => error,
+                Object::Bool(true) => FALSE,
+                Object::Bool(false) => TRUE,
+                Object::Int(0) => TRUE,
+                _ => FALSE,
             },
-            Operator::Neg => match self.expression(expression, env) {
-                Ok(result) => match result {
-                    Object::Int(val) => Object::Int(-val),
-                    _ => Object::Error(format!("Unexpected value type: {:?}", result)),
+            Operator::Neg => match self.expression(expression, env)? {
+                Object::Int(val) => match val.checked_neg() {
+                    Some(val) => Object::Int(val),
+                    None => Object::Error("Integer overflow".to_string()),
                 },
-                Err(_) => panic!()
+                error @ Object::Error(_) => error,
+                result => Object::Error(format!("Unexpected value type: {:?}", result)),
             },
             _ => Object::Error(format!("Unknown operator: {:?}", operator))
         })
@@ -270,10 +776,22 @@ impl Evaluate {
     fn eval_infix(&self, operator: &Operator, left: &Object, right: &Object) -> Result<Object> {
         Ok(match (left, right) {
             (Object::Int(left_val), Object::Int(right_val)) => match operator {
-                Operator::Add => Object::Int(left_val + right_val),
-                Operator::Sub => Object::Int(left_val - right_val),
-                Operator::Mul => Object::Int(left_val * right_val),
-                Operator::Div => Object::Int(left_val / right_val),
+                Operator::Add | Operator::Sub | Operator::Mul => {
+                    let result = match operator {
+                        Operator::Add => left_val.checked_add(*right_val),
+                        Operator::Sub => left_val.checked_sub(*right_val),
+                        _ => left_val.checked_mul(*right_val),
+                    };
+                    match result {
+                        Some(val) => Object::Int(val),
+                        None => Object::Error("Integer overflow".to_string()),
+                    }
+                }
+                Operator::Div => match left_val.checked_div(*right_val) {
+                    Some(val) => Object::Int(val),
+                    None if *right_val == 0 => Object::Error("Division by zero".to_string()),
+                    None => Object::Error("Integer overflow".to_string()),
+                },
                 Operator::Greater => Object::Bool(left_val > right_val),
                 Operator::Less => Object::Bool(left_val < right_val),
                 Operator::Equal => Object::Bool(left_val == right_val),
@@ -349,6 +867,11 @@ mod test {
         test_expression!(result, "3 * 3 * 3 + 10");
         test_expression!(result, "3 * (3 * 3) + 10");
         test_expression!(result, "(5 + 10 * 2 + 15 / 3) * 2 + -10");
+        test_expression!(result, "1 / 0");
+        test_expression!(result, "9223372036854775807 * 2");
+        test_expression!(result, "9223372036854775807 + 1");
+        test_expression!(result, "-9223372036854775807 - 2");
+        test_expression!(result, "let min = -9223372036854775807 - 1; -min");
 
         test_expression!(result, "1 < 2");
         test_expression!(result, "1 > 2");
@@ -366,6 +889,12 @@ mod test {
         test_expression!(result, "if (1 > 2) { 10 }");
         test_expression!(result, "if (1 > 2) { 10 } else { 20 }");
         test_expression!(result, "if (1 < 2) { 10 } else { 20 }");
+        test_expression!(result, "if (1 > 2) { 10 } else if (2 > 1) { 20 } else { 30 }");
+        test_expression!(result, "if (1 > 2) { 10 } else if (2 > 3) { 20 } else { 30 }");
+        test_expression!(result, "if (1 > 2) { 10 } else if (2 > 3) { 20 }");
+        test_expression!(result, "let x = if (1 < 2) { 10 } else { 20 }; x");
+        test_expression!(result, "let x = 5 + if (false) { 1 } else if (true) { 2 } else { 3 }; x");
+        test_expression!(result, "[if (true) { 1 } else { 2 }, if (false) { 1 }]");
 
         test_expression!(result, "return 10;");
         test_expression!(result, "return 10; 9;");
@@ -386,6 +915,15 @@ mod test {
         test_expression!(result, "let x = 100 > 5; y");
         test_expression!(result, "let x = 100; let y = 200; x + y");
 
+        // Errors stop the blocks and expressions they happen in.
+        test_expression!(result, "let g = fn() { 1 / 0; 5 }; g() + 1");
+        test_expression!(result, "if (missing) { 1 } else { 2 }");
+        test_expression!(result, "[missing, 1]; 2");
+        test_expression!(result, "-missing; !missing");
+        test_expression!(result, "let f = fn(a) { a }; f(missing)");
+        test_expression!(result, "[1][missing]");
+        test_expression!(result, "{missing: 1}");
+
         insta::assert_snapshot!(result)
     }
 
@@ -401,8 +939,22 @@ mod test {
         test_expression!(result, "let a = 5; let c = fn(b) { b + 10 }; c(a);");
         test_expression!(result, "let a = 5; let c = fn(b) { b + 10 }; c(2);");
         test_expression!(result, "let o = 5; let a = fn() { o }; let b = fn(m) { m() }; b(a);");
-
-        insta::assert_snapshot!(result)
+        test_expression!(result, "foo(1)");
+        test_expression!(result, "5(1)");
+
+        insta::assert_snapshot!(result);
+
+        // Anonymous functions are named by where they were written.
+        let lexer = MonkeyLexer::new("let f = 1;\n  [fn() { 1 }, fn() { 2 }]");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let Ok(Object::Array(functions)) = Evaluate::new().evaluate(&program) else { panic!() };
+        let names: Vec<_> = functions.iter()
+            .map(|function| match function {
+                Object::Function { name, .. } => name.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(names, ["<fn 2:4>", "<fn 2:16>"]);
     }
 
     #[test]
@@ -416,6 +968,21 @@ mod test {
         insta::assert_snapshot!(result)
     }
 
+    #[test]
+    fn test_interpolation() {
+        let mut result = String::new();
+
+        test_expression!(result, "let name = \"Monkey\"; \"Hello ${name}!\"");
+        test_expression!(result, "let count = 2; \"you have ${count + 1} items\"");
+        test_expression!(result, "\"${1}${true}${[1, 2]}\"");
+        test_expression!(result, "\"nested ${\"a ${\"b\"} c\"} done\"");
+        test_expression!(result, "\"hash ${{\"a\": 5}[\"a\"]}\"");
+        test_expression!(result, "\"escaped \\${name}\"");
+        test_expression!(result, "\"missing ${name}\"");
+
+        insta::assert_snapshot!(result)
+    }
+
     #[test]
     fn test_len() {
         let mut result = String::new();
@@ -435,6 +1002,7 @@ mod test {
         test_expression!(result, "[0, 1, 3][1]");
         test_expression!(result, "[0, 1, 3][-1]");
         test_expression!(result, "[0, 1, 3][5]");
+        test_expression!(result, "[1, 2][2]");
         test_expression!(result, "let a = 2; [0, 1, 3][a]");
         test_expression!(result, "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];");
         test_expression!(result, "let myArray = [1, 2, 3]; myArray[0] + myArray[myArray[0]] + myArray[myArray[0] + myArray[0]];");
@@ -456,6 +1024,148 @@ mod test {
         test_expression!(result, "{10: 5, 13: 5}[10]");
         test_expression!(result, "{true: 15, false: 65}[false]");
         test_expression!(result, "put({1: 15, 2: 65}, 3, 16)[3]");
+        test_expression!(result, "{\"a\": 1}[\"b\"]");
+        test_expression!(result, "{[1]: 2}");
+        test_expression!(result, "{1: 2}[[1]]");
+        test_expression!(result, "put({}, [1], 2)");
+        insta::assert_snapshot!(result)
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let mut result = String::new();
+
+        // Deep enough to overflow the stack of the test thread without tail calls.
+        test_expression!(result, "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; count(10000, 0)");
+        test_expression!(result, "let count = fn(n) { if (n == 0) { return \"done\"; } return count(n - 1); }; count(10000)");
+        test_expression!(result, "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; even(10001)");
+        test_expression!(result, "let sum = fn(arr, acc) { if (len(arr) == 0) { acc } else { sum(rest(arr), acc + first(arr)) } }; sum([1, 2, 3, 4], 0)");
+        test_expression!(result, "let last_of = fn(arr) { len(arr); last(arr) }; last_of([1, 2, 3])");
+        test_expression!(result, "let twice = fn(n) { if (n == 0) { 0 } else { 2 + twice(n - 1) } }; twice(10)");
+        test_expression!(result, "let f = fn(n) { if (n == 0) { x } else { let x = n; f(n - 1) } }; f(3)");
         insta::assert_snapshot!(result)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stack_overflow() {
+        let source = "let down = fn(n) {\n  if (n == 0) { 0 } else { 1 + down(n - 1) }\n};\nlet start = fn(n) { 1 + down(n) };\nstart(100)";
+        let lexer = MonkeyLexer::new(source);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let eval = Evaluate::new().with_max_depth(50);
+        let err = eval.evaluate(&program).unwrap_err();
+        insta::assert_snapshot!(err.to_string());
+
+        // Frames of the failed call are gone, so the evaluator can be reused.
+        let eval = eval.with_max_depth(DEFAULT_MAX_DEPTH);
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(101)));
+
+        // Deeper than the main thread stack allows without growing it.
+        let mut result = String::new();
+        test_expression!(result, "let down = fn(n) { if (n == 0) { 0 } else { 1 + down(n - 1) } }; down(5000)");
+        assert_eq!(result, "Ok(Int(5000))\n");
+
+        // Recursing under a prefix operator overflows the same way.
+        for source in ["let f = fn(n) { -f(n) }; f(1)", "let f = fn(n) { !f(n) }; f(1)"] {
+            let lexer = MonkeyLexer::new(source);
+            let program = Parser::new(&lexer).parse_program().unwrap();
+            let err = Evaluate::new().with_max_depth(50).evaluate(&program).unwrap_err();
+            assert!(matches!(err, Error::StackOverflowError { max_depth: 50, .. }), "{}: {:?}", source, err);
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let lexer = MonkeyLexer::new("let spin = fn(n) { spin(n + 1) }; spin(0)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let eval = Evaluate::new().with_fuel(1000);
+        assert_eq!(eval.evaluate(&program), Err(Error::BudgetExhaustedError(Budget::Fuel(1000))));
+        assert_eq!(eval.fuel_remaining(), Some(0));
+
+        let eval = Evaluate::new().with_deadline(Instant::now() + std::time::Duration::from_millis(50));
+        assert_eq!(eval.evaluate(&program), Err(Error::BudgetExhaustedError(Budget::Deadline)));
+
+        // Running out inside the operand of a prefix operator.
+        let lexer = MonkeyLexer::new("let f = fn(n) { -f(n) }; f(1)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new().with_fuel(10);
+        assert_eq!(eval.evaluate(&program), Err(Error::BudgetExhaustedError(Budget::Fuel(10))));
+
+        // Counting is deterministic: `1 + 2` is a statement and three expressions.
+        let lexer = MonkeyLexer::new("1 + 2");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new().with_fuel(4);
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(3)));
+        assert_eq!(eval.fuel_remaining(), Some(0));
+        assert_eq!(eval.evaluate(&program), Err(Error::BudgetExhaustedError(Budget::Fuel(4))));
+    }
+
+    #[test]
+    fn test_heap() {
+        let object = size_of::<Object>();
+
+        // The literal owns its items, reading `a` shares them.
+        let lexer = MonkeyLexer::new("let a = [\"ab\", \"c\"]; len(a)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new();
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(2)));
+        assert_eq!(eval.peak_heap(), 2 * object + 3);
+
+        // Temporaries of top level statements are released once they ran.
+        let source = format!("let a = \"{}\";{}", "x".repeat(1000), " len(a + \"y\");".repeat(200));
+        let lexer = MonkeyLexer::new(&source);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new().with_max_heap(50_000);
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(1001)));
+        assert_eq!(eval.peak_heap(), 1000 + 1 + 1001);
+
+        // Each string is twice the last, only the ones still referenced count.
+        let lexer = MonkeyLexer::new("let grow = fn(s, n) { if (n == 0) { len(s) } else { grow(s + s, n - 1) } }; grow(\"ab\", 20)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new().with_max_heap(1 << 20);
+        assert_eq!(eval.evaluate(&program), Err(Error::BudgetExhaustedError(Budget::Heap(1 << 20))));
+
+        let eval = Evaluate::new().with_max_heap(1 << 24);
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(2 << 20)));
+        assert!(eval.peak_heap() < 1 << 24);
+
+        // Intermediate arrays are released between tail calls.
+        let lexer = MonkeyLexer::new("let build = fn(arr, n) { if (n == 0) { len(arr) } else { build(push(arr, n), n - 1) } }; build([], 1000)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        let eval = Evaluate::new().with_max_heap(1000 * object * 4);
+        assert_eq!(eval.evaluate(&program), Ok(Object::Int(1000)));
+    }
+
+    #[test]
+    fn test_args_and_exit() {
+        let lexer = MonkeyLexer::new("if (len(args) == 0) { exit(4) }; last(args)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let eval = Evaluate::new().with_args(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(eval.evaluate(&program), Ok(Object::String("c".to_string())));
+        assert_eq!(Evaluate::new().evaluate(&program), Err(Error::ExitError(4)));
+
+        // Exiting unwinds every call.
+        let lexer = MonkeyLexer::new("let f = fn(code) { exit(code); 1 }; f(3); 2");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        assert_eq!(Evaluate::new().evaluate(&program), Err(Error::ExitError(3)));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut result = String::new();
+
+        test_expression!(result, r#"json_parse("{\"b\": [1, true, null], \"a\": \"x\"}")["b"]"#);
+        test_expression!(result, r#"json_parse("[1, 2")"#);
+        test_expression!(result, r#"json_parse("1.5")"#);
+        test_expression!(result, r#"json_stringify({"b": [1, true], "a": {2: "x"}})"#);
+        test_expression!(result, r#"json_stringify(json_parse("{\"z\": 1, \"a\": [null, \"s\"]}"), true)"#);
+        test_expression!(result, r#"json_stringify([fn(x) { x }])"#);
+        test_expression!(result, r#"json_stringify({1: "a", "1": "b"})"#);
+        test_expression!(result, r#"json_stringify({"a": 1}, 1)"#);
+        test_expression!(result, r#"json_stringify(1, true, 2)"#);
+
+        insta::assert_snapshot!(result);
+    }
+}
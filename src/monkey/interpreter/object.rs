@@ -4,11 +4,12 @@ use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+use crate::monkey::interpreter::capability::Capabilities;
 use crate::monkey::interpreter::Environment;
 use crate::monkey::parser::ast::{Identifier, Program};
 use crate::monkey::Result;
 
-pub type BuiltInFn = fn(Vec<Object>) -> Result<Object>;
+pub type BuiltInFn = fn(&Capabilities, Vec<Object>) -> Result<Object>;
 
 #[derive(Clone, PartialEq)]
 pub enum Object {
@@ -17,6 +18,8 @@ pub enum Object {
     Bool(bool),
     Return(Box<Object>),
     Function {
+        /// The `let` binding the function was defined by, `<fn line:column>` for anonymous ones.
+        name: String,
         ident: Vec<Identifier>,
         program: Box<Program>,
         env: Rc<RefCell<Environment>>,
@@ -24,6 +27,8 @@ pub enum Object {
     BuiltIn {
         name: String,
         params: usize,
+        /// Number of trailing `params` which may be left out.
+        optional: usize,
         program: BuiltInFn,
     },
     String(String),
@@ -32,6 +37,33 @@ pub enum Object {
     Hash(HashMap<Object, Object>),
 }
 
+impl Object {
+    /// Estimated bytes held on the heap by strings, arrays and hashes in this value.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Object::Array(arr) => self.shallow_heap_size() + arr.iter().map(Object::heap_size).sum::<usize>(),
+            Object::Hash(map) => self.shallow_heap_size() + map.iter().map(|(key, val)| key.heap_size() + val.heap_size()).sum::<usize>(),
+            Object::Return(val) => val.heap_size(),
+            _ => self.shallow_heap_size(),
+        }
+    }
+
+    /// Whether the value can be the key of a hash.
+    pub fn is_hashable(&self) -> bool {
+        matches!(self, Object::Int(_) | Object::Bool(_) | Object::String(_))
+    }
+
+    /// Like `heap_size`, leaving out the heap held by the items of an array or hash.
+    pub fn shallow_heap_size(&self) -> usize {
+        match self {
+            Object::String(str) => str.len(),
+            Object::Array(arr) => arr.len() * size_of::<Object>(),
+            Object::Hash(map) => map.len() * 2 * size_of::<Object>(),
+            _ => 0,
+        }
+    }
+}
+
 // FIXME: is there any easier way to do this?
 impl Debug for Object {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -42,10 +74,10 @@ impl Debug for Object {
             Object::Return(val) => write!(f, "Return({:?})", val),
 
             // env could contain self - to prevent inf recursion we omit printing env.
-            Object::Function { ident, program, env } => write!(f, "Function {{ ident: {:?}, program: {:?} }}", ident, program),
+            Object::Function { ident, program, .. } => write!(f, "Function {{ ident: {:?}, program: {:?} }}", ident, program),
 
             // Omit program since we use this for test and we want predictable output.
-            Object::BuiltIn { name, params, program } => write!(f, "BuiltIn {{ name: {:?}, params: {:?} }}", name, params),
+            Object::BuiltIn { name, params, .. } => write!(f, "BuiltIn {{ name: {:?}, params: {:?} }}", name, params),
 
             Object::String(val) => write!(f, "String({:?})", val),
             Object::Error(val) => write!(f, "Error({:?})", val),
@@ -101,7 +133,7 @@ impl Hash for Object {
             Object::Bool(val) => val.hash(state),
             Object::String(val) => val.hash(state),
 
-            _ => todo!("Not supported"),
+            _ => unreachable!("unusable as hash key: {:?}", self),
         }
     }
 }
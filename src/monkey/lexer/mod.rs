@@ -1,90 +1,201 @@
-use std::cell::Cell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::fs;
 use std::rc::Rc;
-use crate::monkey::error::Error::{InvalidTokenError, TodoError, InvalidCharError};
+use crate::monkey::error::Error::{InvalidTokenError, TodoError, InvalidCharError, InvalidEscapeError, UnterminatedStringError};
 use crate::monkey::Result;
 
-use crate::monkey::helper::{is_digit, is_identifier, is_whitespace};
+use crate::monkey::helper::{is_digit, is_identifier, is_identifier_continue, is_whitespace};
 use crate::monkey::token::{Span, Token, TokenType};
 
-pub trait Lexer {
-    fn token(&self) -> Result<Token>;
+pub trait Lexer<'a> {
+    fn token(&self) -> Result<Token<'a>>;
 }
 
-pub struct MonkeyLexer {
+/// Lexer working directly over the bytes of the source - tokens borrow their literal from it.
+pub struct MonkeyLexer<'a> {
     /// Input
-    input: Vec<char>,
+    input: &'a str,
 
     column: Cell<usize>,
+    /// Byte offset of `ch` within the input.
     position: Cell<usize>,
-    read_position: Cell<usize>,
     line: Cell<usize>,
     ch: Cell<char>,
+
+    /// Every `${` interpolation we are currently inside of, innermost last.
+    interpolation: RefCell<Vec<Interpolation>>,
+
+    /// Set once the iterator returned `EndOfFile` or an error.
+    finished: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Quote {
+    /// `"..."`
+    Single,
+    /// `"""..."""`
+    Triple,
 }
 
-impl Lexer for MonkeyLexer {
-    fn token(&self) -> Result<Token> {
+impl Quote {
+    fn len(&self) -> usize {
+        match self {
+            Quote::Single => 1,
+            Quote::Triple => 3,
+        }
+    }
+}
+
+/// String literal suspended at a `${` until its matching `}` is found.
+struct Interpolation {
+    depth: usize,
+    quote: Quote,
+    opening: Span,
+}
+
+/// Where a token started.
+#[derive(Copy, Clone)]
+struct Mark {
+    position: usize,
+    column: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> for MonkeyLexer<'a> {
+    fn token(&self) -> Result<Token<'a>> {
         self.read()
     }
 }
 
+impl<'a> Iterator for MonkeyLexer<'a> {
+    type Item = Result<Token<'a>>;
 
-impl MonkeyLexer {
-    pub fn new(input: &str) -> Self {
-        let input: Vec<char> = input.chars().collect();
-        let ch: char = input[0];
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.read();
+        self.finished = !matches!(&result, Ok(token) if token.token_type != TokenType::EndOfFile);
+
+        Some(result)
+    }
+}
+
+impl<'a> MonkeyLexer<'a> {
+    pub fn new(input: &'a str) -> Self {
         Self {
             input,
             column: Cell::new(0),
             position: Cell::new(0),
-            read_position: Cell::new(1),
             line: Cell::new(0),
-            ch: Cell::new(ch.into()),
+            ch: Cell::new(char_at(input, 0)),
+            interpolation: RefCell::new(Vec::new()),
+            finished: false,
         }
     }
 
+    fn at_end(&self) -> bool {
+        self.position.get() >= self.input.len()
+    }
+
     fn peek(&self) -> char {
-        self.input[self.read_position.get()]
+        self.peek_at(1)
     }
 
-    fn handle_whitespace(&self) {
-        while is_whitespace(self.ch.get()) {
-            self.next()
+    fn peek_at(&self, offset: usize) -> char {
+        let position = self.position.get();
+        if position >= self.input.len() {
+            return '\0';
+        }
+
+        self.input[position..].chars().nth(offset).unwrap_or('\0')
+    }
+
+    fn skip(&self, count: usize) {
+        for _ in 0..count {
+            self.next();
+        }
+    }
+
+    fn is_triple_quote(&self) -> bool {
+        self.ch.get() == '"' && self.peek() == '"' && self.peek_at(2) == '"'
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            position: self.position.get(),
+            column: self.column.get(),
+            line: self.line.get(),
         }
     }
 
-    fn handle_single_token(&self, token_type: TokenType) -> Result<Token> {
-        let token = self.tokenize(1, 0, token_type);
+    /// Skip whitespace and `//` comments.
+    fn handle_trivia(&self) {
+        loop {
+            while !self.at_end() && is_whitespace(self.ch.get()) {
+                self.next()
+            }
+
+            if self.ch.get() != '/' || self.peek() != '/' {
+                break;
+            }
+            while !self.at_end() && self.ch.get() != '\n' {
+                self.next()
+            }
+        }
+    }
+
+    fn handle_single_token(&self, token_type: TokenType) -> Result<Token<'a>> {
+        let mark = self.mark();
         self.next();
 
-        token
+        self.tokenize(mark, token_type)
     }
 
-    fn handle_double_token(&self, token_type: TokenType) -> Result<Token> {
-        let token = self.tokenize(2, 0, token_type);
+    fn handle_double_token(&self, token_type: TokenType) -> Result<Token<'a>> {
+        let mark = self.mark();
         self.next();
         self.next();
 
-        token
+        self.tokenize(mark, token_type)
     }
 
-    fn read_operator_double(&self, token_type: TokenType) -> Result<Token> {
+    fn read_operator_double(&self, token_type: TokenType) -> Result<Token<'a>> {
         if self.peek() == '=' {
             match self.ch.get() {
                 '=' => self.handle_double_token(TokenType::Equal),
                 '!' => self.handle_double_token(TokenType::NotEqual),
                 '<' => self.handle_double_token(TokenType::GreaterThanEqual),
                 '>' => self.handle_double_token(TokenType::LessThanEqual),
-                c @ _ => Err(TodoError(format!("unsupported token {}", c))),
+                c => Err(TodoError(format!("unsupported token {}", c))),
             }
         } else {
             self.handle_single_token(token_type)
         }
     }
 
-    fn read(&self) -> Result<Token> {
-        self.handle_whitespace();
+    fn read(&self) -> Result<Token<'a>> {
+        let trivia_start = self.position.get();
+        self.handle_trivia();
+
+        let mut token = self.read_token()?;
+        token.trivia = Cow::Borrowed(&self.input[trivia_start..token.span.byte_start]);
+
+        Ok(token)
+    }
+
+    fn read_token(&self) -> Result<Token<'a>> {
+        if self.at_end() {
+            // The string around a `${` which was never closed.
+            if let Some(interpolation) = self.interpolation.borrow().last() {
+                return Err(UnterminatedStringError(interpolation.opening.clone()));
+            }
+            return self.tokenize(self.mark(), TokenType::EndOfFile);
+        }
+
         match self.ch.get() {
             '=' => self.read_operator_double(TokenType::Assign),
             '+' => self.handle_single_token(TokenType::Plus),
@@ -98,137 +209,346 @@ impl MonkeyLexer {
             '(' => self.handle_single_token(TokenType::LeftParen),
             ')' => self.handle_single_token(TokenType::RightParen),
             ',' => self.handle_single_token(TokenType::Comma),
-            '{' => self.handle_single_token(TokenType::LeftBrace),
-            '}' => self.handle_single_token(TokenType::RightBrace),
+            '{' => self.handle_left_brace(),
+            '}' => self.handle_right_brace(),
             '[' => self.handle_single_token(TokenType::LeftBracket),
             ']' => self.handle_single_token(TokenType::RightBracket),
             ':' => self.handle_single_token(TokenType::Colon),
             '"' => self.handle_string_token(),
-            '\0' => self.tokenize(0, 0, TokenType::EndOfFile),
+            'r' if self.peek() == '"' => self.handle_raw_string_token(),
             ch if is_identifier(ch) => self.read_identifier(),
             ch if is_digit(ch) => self.read_digit(),
-            c @ _ => Err(TodoError(format!("unsupported token {}", c))),
+            c => Err(TodoError(format!("unsupported token {}", c))),
         }
     }
 
-    fn read_identifier(&self) -> Result<Token> {
-        let position = self.position.get();
-        while is_identifier(self.ch.get()) {
+    fn read_identifier(&self) -> Result<Token<'a>> {
+        let mark = self.mark();
+        while !self.at_end() && is_identifier_continue(self.ch.get()) {
             self.next();
         }
-        let end_position = self.position.get();
-        let length = end_position - position;
-
-        let identifier: &str = &self.input[position..end_position].iter().collect::<String>();
-        match identifier {
-            "let" => self.tokenize(length, length, TokenType::Let),
-            "fn" => self.tokenize(length, length, TokenType::Function),
-            "true" => self.tokenize(length, length, TokenType::True),
-            "false" => self.tokenize(length, length, TokenType::False),
-            "if" => self.tokenize(length, length, TokenType::If),
-            "else" => self.tokenize(length, length, TokenType::Else),
-            "return" => self.tokenize(length, length, TokenType::Return),
-            _ => self.tokenize(length, length, TokenType::Identifier),
-        }
+
+        let token_type = match &self.input[mark.position..self.position.get()] {
+            "let" => TokenType::Let,
+            "fn" => TokenType::Function,
+            "true" => TokenType::True,
+            "false" => TokenType::False,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "return" => TokenType::Return,
+            _ => TokenType::Identifier,
+        };
+
+        self.tokenize(mark, token_type)
     }
 
-    fn read_digit(&self) -> Result<Token> {
-        let position = self.position.get();
-        while is_digit(self.ch.get()) {
+    fn read_digit(&self) -> Result<Token<'a>> {
+        let mark = self.mark();
+        while !self.at_end() && is_digit(self.ch.get()) {
             self.next()
         }
-        let end_position = self.position.get();
-        let length = end_position - position;
 
-        self.tokenize(length, length, TokenType::Integer)
+        self.tokenize(mark, TokenType::Integer)
     }
 
-    /// Move the processing position to the next token
+    /// Move the processing position to the next character
     fn next(&self) {
-        if self.input.len() <= self.read_position.get() {
-            self.ch.set(0.into());
+        if self.at_end() {
             self.column.set(self.column.get() + 1);
+            return;
+        }
+
+        let ch = self.ch.get();
+        let position = self.position.get() + ch.len_utf8();
+
+        // A trailing newline does not start a new line of its own.
+        if ch == '\n' && position < self.input.len() {
+            self.line.set(self.line.get() + 1);
+            self.column.set(0)
         } else {
-            if self.input[self.position.get()] == '\n' {
-                self.line.set(self.line.get() + 1);
-                self.column.set(0)
-            } else {
-                self.column.set(self.column.get() + 1);
+            self.column.set(self.column.get() + 1);
+        }
+
+        self.position.set(position);
+        self.ch.set(char_at(self.input, position));
+    }
+
+    fn handle_left_brace(&self) -> Result<Token<'a>> {
+        if let Some(interpolation) = self.interpolation.borrow_mut().last_mut() {
+            interpolation.depth += 1;
+        }
+
+        self.handle_single_token(TokenType::LeftBrace)
+    }
+
+    fn handle_right_brace(&self) -> Result<Token<'a>> {
+        let mut stack = self.interpolation.borrow_mut();
+        if let Some(interpolation) = stack.last_mut() {
+            if interpolation.depth == 0 {
+                // This brace closes a `${` - the rest is the string literal again.
+                let Interpolation { quote, opening, .. } = stack.pop().unwrap();
+                drop(stack);
+
+                let mark = self.mark();
+                self.next();
+
+                return self.read_string(
+                    mark, quote, opening,
+                    TokenType::InterpolationEnd, TokenType::InterpolationMiddle,
+                );
             }
-            self.ch.set(self.input[self.read_position.get()])
+            interpolation.depth -= 1;
         }
+        drop(stack);
 
-        self.position.set(self.position.get() + 1);
-        self.read_position.set(self.read_position.get() + 1);
+        self.handle_single_token(TokenType::RightBrace)
     }
 
-    fn handle_string_token(&self) -> Result<Token> {
-        let position = self.position.get();
-        let start = self.column.get();
+    /// Start of a `"` or `"""` string literal.
+    fn handle_string_token(&self) -> Result<Token<'a>> {
+        let quote = if self.is_triple_quote() { Quote::Triple } else { Quote::Single };
+        let mark = self.mark();
+        let opening = self.quote_span(mark, quote.len());
 
-        self.next();
+        self.skip(quote.len());
+
+        if quote == Quote::Triple {
+            self.skip_leading_newline();
+        }
+
+        self.read_string(mark, quote, opening, TokenType::String, TokenType::InterpolationStart)
+    }
+
+    /// Multi-line strings usually start on the line after the quotes.
+    fn skip_leading_newline(&self) {
+        if self.ch.get() == '\r' && self.peek() == '\n' {
+            self.next();
+        }
+        if self.ch.get() == '\n' {
+            self.next();
+        }
+    }
 
-        let mut chars: Vec<char> = Vec::new();
+    /// Read the string literal body up to the closing quote.
+    ///
+    /// The literal ends either at the closing quote - producing `closed` - or at a `${`, producing
+    /// `interpolated` and leaving the lexer to tokenize the embedded expression. The literal is
+    /// only copied out of the source when escapes or `\r\n` line endings need to be rewritten.
+    fn read_string(
+        &self,
+        mark: Mark,
+        quote: Quote,
+        opening: Span,
+        closed: TokenType,
+        interpolated: TokenType,
+    ) -> Result<Token<'a>> {
+        let content_start = self.position.get();
+        let mut cooked: Option<String> = None;
+
+        let (token_type, content_end) = loop {
+            if self.at_end() {
+                return Err(UnterminatedStringError(opening));
+            }
+
+            let content_end = self.position.get();
+            match self.ch.get() {
+                '"' if quote == Quote::Single || self.is_triple_quote() => {
+                    self.skip(quote.len() - 1);
+                    break (closed, content_end);
+                }
+                '\\' => {
+                    let cooked = cooked.get_or_insert_with(|| self.input[content_start..content_end].to_string());
+                    self.next();
+                    cooked.push(self.read_escape()?);
+                }
+                '\r' if self.peek() == '\n' => {
+                    cooked.get_or_insert_with(|| self.input[content_start..content_end].to_string());
+                }
+                '$' if self.peek() == '{' => {
+                    self.next();
+                    self.interpolation.borrow_mut().push(Interpolation { depth: 0, quote, opening });
+                    break (interpolated, content_end);
+                }
+                ch => if let Some(cooked) = &mut cooked {
+                    cooked.push(ch)
+                },
+            }
+            self.next();
+        };
+
+        let literal = match cooked {
+            Some(cooked) => Cow::Owned(cooked),
+            None => Cow::Borrowed(&self.input[content_start..content_end]),
+        };
+
+        self.string_token(mark, token_type, literal)
+    }
+
+    /// Decode the escape sequence following a `\`, leaving the lexer on its last character.
+    fn read_escape(&self) -> Result<char> {
+        Ok(match self.ch.get() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            '$' => '$',
+            'x' => {
+                let mut code = 0;
+                for _ in 0..2 {
+                    self.next();
+                    code = code * 16 + self.read_hex_digit()?;
+                }
+
+                if code > 0x7F {
+                    return Err(InvalidEscapeError(format!("\\x{:02X} is out of range, expected at most \\x7F", code)));
+                }
+                char::from(code as u8)
+            }
+            'u' => {
+                self.next();
+                if self.ch.get() != '{' {
+                    return Err(InvalidCharError(self.ch.get()));
+                }
 
-        while self.ch.get() != '"' && self.ch.get() != '\0' {
-            if self.ch.get() == '\\' {
+                let mut code: u32 = 0;
+                let mut digits = 0;
                 self.next();
-                match self.ch.get() {
-                    'n' => chars.push('\n'),
-                    't' => chars.push('\t'),
-                    '"' => chars.push('"'),
-                    '\\' => chars.push('\\'),
-                    val @ _ => return Err(InvalidCharError(self.ch.get())),
+                while self.ch.get() != '}' {
+                    if digits == 6 {
+                        return Err(InvalidEscapeError("\\u{...} takes at most 6 hex digits".to_string()));
+                    }
+                    code = code * 16 + self.read_hex_digit()?;
+                    digits += 1;
+                    self.next();
+                }
+
+                if digits == 0 {
+                    return Err(InvalidEscapeError("\\u{} is missing the code point".to_string()));
+                }
+                match char::from_u32(code) {
+                    Some(ch) => ch,
+                    None => return Err(InvalidEscapeError(format!("\\u{{{:X}}} is not a valid code point", code))),
                 }
-            } else {
-                chars.push(self.ch.get())
             }
-            self.next()
+            ch => return Err(InvalidCharError(ch)),
+        })
+    }
+
+    fn read_hex_digit(&self) -> Result<u32> {
+        match self.ch.get().to_digit(16) {
+            Some(digit) => Ok(digit),
+            None => Err(InvalidCharError(self.ch.get())),
+        }
+    }
+
+    /// Raw string literal `r"..."` or `r"""..."""` - no escapes or interpolation are processed.
+    fn handle_raw_string_token(&self) -> Result<Token<'a>> {
+        let mark = self.mark();
+
+        self.next();
+        let quote = if self.is_triple_quote() { Quote::Triple } else { Quote::Single };
+        let opening = self.quote_span(mark, quote.len() + 1);
+
+        self.skip(quote.len());
+        if quote == Quote::Triple {
+            self.skip_leading_newline();
+        }
+
+        let content_start = self.position.get();
+        let mut cooked: Option<String> = None;
+        while !(self.ch.get() == '"' && (quote == Quote::Single || self.is_triple_quote())) {
+            if self.at_end() {
+                return Err(UnterminatedStringError(opening));
+            }
+
+            let content_end = self.position.get();
+            match self.ch.get() {
+                '\r' if self.peek() == '\n' => {
+                    cooked.get_or_insert_with(|| self.input[content_start..content_end].to_string());
+                }
+                ch => if let Some(cooked) = &mut cooked {
+                    cooked.push(ch)
+                },
+            }
+            self.next();
+        }
+        let content_end = self.position.get();
+        self.skip(quote.len() - 1);
+
+        let literal = match cooked {
+            Some(cooked) => Cow::Owned(cooked),
+            None => Cow::Borrowed(&self.input[content_start..content_end]),
+        };
+
+        self.string_token(mark, TokenType::String, literal)
+    }
+
+    /// Span of the quote starting at `mark`, used to report unterminated strings.
+    fn quote_span(&self, mark: Mark, length: usize) -> Span {
+        Span {
+            start: mark.column,
+            end: mark.column + length - 1,
+            line_start: mark.line,
+            line_end: mark.line,
+            byte_start: mark.position,
+            byte_end: mark.position + length,
         }
+    }
 
-        let end_position = self.position.get();
-        let length = end_position - position;
-        let end = self.column.get();
+    /// Finish a string token while the lexer is still on its closing delimiter.
+    fn string_token(&self, mark: Mark, token_type: TokenType, literal: Cow<'a, str>) -> Result<Token<'a>> {
+        let (end, line_end) = (self.column.get(), self.line.get());
 
         self.next();
 
-        // FIXME: there is some code duplication here - some how merge this with tokenizer.
         Ok(Token {
-            token_type: TokenType::String,
-            literal: chars.iter().collect::<String>(),
+            token_type,
+            literal,
             span: Span {
-                start,
+                start: mark.column,
                 end,
-                line_start: self.line.get(),
-                line_end: self.line.get(),
+                line_start: mark.line,
+                line_end,
+                byte_start: mark.position,
+                byte_end: self.position.get(),
             },
+            trivia: Cow::Borrowed(""),
         })
     }
 
-    fn tokenize(&self, length: usize, offset: usize, token_type: TokenType) -> Result<Token> {
-        let position = self.position.get() - offset;
-
-        let literal = if length > 0 {
-            self.input[position..position + length].to_vec()
-        } else {
-            vec![]
-        };
+    /// Token from `mark` up to the current position.
+    fn tokenize(&self, mark: Mark, token_type: TokenType) -> Result<Token<'a>> {
+        let position = self.position.get();
+        let column = self.column.get();
 
-        let column = self.column.get() - offset;
-        let end = if length > 0 { column + length - 1 } else { column };
+        let end = if position > mark.position { column - 1 } else { column };
         Ok(Token {
             token_type,
-            literal: literal.iter().collect::<String>(),
+            literal: Cow::Borrowed(&self.input[mark.position..position]),
             span: Span {
-                start: column,
+                start: mark.column,
                 end,
-                line_start: self.line.get(),
-                line_end: self.line.get(),
+                line_start: mark.line,
+                line_end: mark.line,
+                byte_start: mark.position,
+                byte_end: position,
             },
+            trivia: Cow::Borrowed(""),
         })
     }
 }
 
+/// Character starting at byte `position` - `\0` past the end of the input.
+fn char_at(input: &str, position: usize) -> char {
+    match input.as_bytes().get(position) {
+        None => '\0',
+        Some(byte) if byte.is_ascii() => *byte as char,
+        Some(_) => input[position..].chars().next().unwrap_or('\0'),
+    }
+}
+
 pub fn run_lexer(source: &str) -> Result<String> {
     let contents = fs::read_to_string(source).unwrap();
     eval_lexer(&contents)
@@ -236,24 +556,14 @@ pub fn run_lexer(source: &str) -> Result<String> {
 
 pub fn eval_lexer(contents: &String) -> Result<String> {
     let lines: Vec<usize> = contents
-        .chars()
-        .enumerate()
+        .char_indices()
         .filter(|(_, ch)| *ch == '\n')
         .map(|(idx, _)| idx)
         .collect();
 
-    let lexer = MonkeyLexer::new(&*contents);
-
     let mut tokens = VecDeque::new();
-    let mut stop_loop = false;
-    while !stop_loop {
-        let result = lexer.token();
-        if let Ok(token) = result {
-            stop_loop = token.token_type == TokenType::EndOfFile;
-            tokens.push_back(token);
-        } else if let Err(message) = result {
-            return Err(message);
-        }
+    for token in MonkeyLexer::new(contents) {
+        tokens.push_back(token?);
     }
 
     let snapshot = token_snapshot(&contents, &lines, &tokens);
@@ -261,34 +571,59 @@ pub fn eval_lexer(contents: &String) -> Result<String> {
     Ok(snapshot)
 }
 
-pub fn token_snapshot(program: &String, lines: &Vec<usize>, tokens: &VecDeque<Token>) -> String {
+/// Byte range of `line` within `program` - including the newlines preceding and ending it.
+fn line_range(program: &str, lines: &[usize], line: usize) -> (usize, usize) {
+    let start = if line != 0 {
+        lines[line - 1]
+    } else {
+        0
+    };
+
+    let end = if line < lines.len() {
+        lines[line] + 1
+    } else {
+        program.len()
+    };
+
+    (start, end)
+}
+
+/// Append `line` to `output`, ending it with a newline even when the program does not.
+fn push_line(output: &mut String, line: &str) {
+    *output += line;
+    if !line.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+pub fn token_snapshot(program: &String, lines: &Vec<usize>, tokens: &VecDeque<Token<'_>>) -> String {
     let mut output = String::new();
     let mut working_line = usize::MAX;
     for token in tokens {
-        if token.span.line_start != token.span.line_end {
-            panic!("multiline command not supported 😞")
-        }
-
         if token.span.line_start != working_line {
             working_line = token.span.line_start;
-            let start = if working_line != 0 {
-                lines[working_line - 1]
-            } else {
-                0
-            };
-
-            let end = if working_line < lines.len() {
-                lines[working_line]
-            } else {
-                program.len() - 1
-            };
-            output += &program[start..=end];
+            let (start, end) = line_range(program, lines, working_line);
+            push_line(&mut output, &program[start..end]);
         }
 
+        let width = if token.span.line_start != token.span.line_end {
+            // Only underline the first line of a multi-line token.
+            let (start, end) = line_range(program, lines, working_line);
+            program[start..end].trim_matches('\n').chars().count() - token.span.start
+        } else {
+            token.span.end + 1 - token.span.start
+        };
+
         output += &" ".repeat(token.span.start);
-        output += &"^".repeat(token.span.end + 1 - token.span.start);
+        output += &"^".repeat(width);
         output += &format!(" {token:?}");
-        output += &"\n";
+        output += "\n";
+
+        while working_line < token.span.line_end {
+            working_line += 1;
+            let (start, end) = line_range(program, lines, working_line);
+            push_line(&mut output, &program[start + 1..end]);
+        }
     }
 
     output
@@ -296,6 +631,8 @@ pub fn token_snapshot(program: &String, lines: &Vec<usize>, tokens: &VecDeque<To
 
 #[cfg(test)]
 mod test {
+    use crate::monkey::token::{Trivia, TriviaKind};
+
     use super::*;
 
     #[test]
@@ -304,4 +641,151 @@ mod test {
 
         insta::assert_snapshot!(result)
     }
+
+    #[test]
+    fn test_lexer_line_end() {
+        assert_eq!(eval_lexer(&String::new()).unwrap(), "\n^ Token(EndOfFile, \"\" [L0-0:0])\n");
+        assert_eq!(eval_lexer(&"x\n= 1".to_string()).unwrap(), "x\n^ Token(Identifier, \"x\" [L0-0:0])\n\n= 1\n^ Token(Assign, \"=\" [L1-0:0])\n  ^ Token(Integer, \"1\" [L1-2:2])\n   ^ Token(EndOfFile, \"\" [L1-3:3])\n");
+    }
+
+    #[test]
+    fn test_lexer_interpolation() {
+        let result = run_lexer("monkey/test_lexer_interpolation.mky").unwrap();
+
+        insta::assert_snapshot!(result)
+    }
+
+    #[test]
+    fn test_lexer_identifier() {
+        let result = run_lexer("monkey/test_lexer_identifier.mky").unwrap();
+
+        insta::assert_snapshot!(result)
+    }
+
+    #[test]
+    fn test_lexer_string() {
+        let result = run_lexer("monkey/test_lexer_string.mky").unwrap();
+
+        insta::assert_snapshot!(result)
+    }
+
+    #[test]
+    fn test_lexer_string_error() {
+        let lex = |source: &str| {
+            let lexer = MonkeyLexer::new(source);
+            loop {
+                match lexer.token() {
+                    Ok(token) if token.token_type == TokenType::EndOfFile => return Ok(token.token_type),
+                    Ok(_) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        };
+
+        let span = |start, end, line, byte_start| Span {
+            start,
+            end,
+            line_start: line,
+            line_end: line,
+            byte_start,
+            byte_end: byte_start + end + 1 - start,
+        };
+
+        assert_eq!(lex("let a = \"abc"), Err(UnterminatedStringError(span(8, 8, 0, 8))));
+        assert_eq!(lex("\n  \"\"\"abc\n\"\""), Err(UnterminatedStringError(span(2, 4, 1, 3))));
+        assert_eq!(lex("r\"abc"), Err(UnterminatedStringError(span(0, 1, 0, 0))));
+        assert_eq!(lex("\"a ${b} c"), Err(UnterminatedStringError(span(0, 0, 0, 0))));
+        assert_eq!(lex("x \"${1"), Err(UnterminatedStringError(span(2, 2, 0, 2))));
+        assert_eq!(lex("\"a ${\"b ${1"), Err(UnterminatedStringError(span(5, 5, 0, 5))));
+        assert_eq!(lex("\"\\q\""), Err(InvalidCharError('q')));
+        assert_eq!(lex("\"\\xZ1\""), Err(InvalidCharError('Z')));
+        assert_eq!(lex("\"\\x80\""), Err(InvalidEscapeError("\\x80 is out of range, expected at most \\x7F".to_string())));
+        assert_eq!(lex("\"\\u{D800}\""), Err(InvalidEscapeError("\\u{D800} is not a valid code point".to_string())));
+        assert_eq!(lex("\"\\u{}\""), Err(InvalidEscapeError("\\u{} is missing the code point".to_string())));
+    }
+
+    #[test]
+    fn test_lexer_iterator() {
+        let types = |source: &str| {
+            MonkeyLexer::new(source)
+                .map(|token| token.map(|token| token.token_type))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(types(""), vec![Ok(TokenType::EndOfFile)]);
+        assert_eq!(types("  \n\t"), vec![Ok(TokenType::EndOfFile)]);
+        assert_eq!(types("x;"), vec![Ok(TokenType::Identifier), Ok(TokenType::Semicolon), Ok(TokenType::EndOfFile)]);
+        assert_eq!(types("x \"\\q\" y"), vec![Ok(TokenType::Identifier), Err(InvalidCharError('q'))]);
+    }
+
+    #[test]
+    fn test_lexer_crlf() {
+        let tokens: Vec<Token> = MonkeyLexer::new("let a = 1;\r\nlet b = \"x\r\ny\";\r\n")
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let spans: Vec<String> = tokens.iter().map(|token| format!("{:?}", token)).collect();
+        assert_eq!(spans, vec![
+            "Token(Let, \"let\" [L0-0:2])",
+            "Token(Identifier, \"a\" [L0-4:4])",
+            "Token(Assign, \"=\" [L0-6:6])",
+            "Token(Integer, \"1\" [L0-8:8])",
+            "Token(Semicolon, \";\" [L0-9:9])",
+            "Token(Let, \"let\" [L1-0:2])",
+            "Token(Identifier, \"b\" [L1-4:4])",
+            "Token(Assign, \"=\" [L1-6:6])",
+            "Token(String, \"x\\ny\" [L1-8:L2-1])",
+            "Token(Semicolon, \";\" [L2-2:2])",
+            "Token(EndOfFile, \"\" [L2-5:5])",
+        ]);
+    }
+
+    #[test]
+    fn test_lexer_borrows_source() {
+        let source = "let name = \"plain\" + \"esc\\n\";";
+        let tokens: Vec<Token> = MonkeyLexer::new(source).collect::<Result<_>>().unwrap();
+
+        for token in tokens.iter().filter(|token| token.token_type != TokenType::String) {
+            assert_eq!(&source[token.span.byte_start..token.span.byte_end], token.literal);
+        }
+        assert!(matches!(tokens[1].literal, Cow::Borrowed("name")));
+        assert!(matches!(tokens[3].literal, Cow::Borrowed("plain")));
+        assert!(matches!(tokens[5].literal, Cow::Owned(_)));
+        assert_eq!(&source[tokens[5].span.byte_start..tokens[5].span.byte_end], "\"esc\\n\"");
+    }
+
+    #[test]
+    fn test_lexer_trivia() {
+        let source = "// header\nlet x = 1; // trailing\n\n  // lone\nx / 2\n";
+        let tokens: Vec<Token> = MonkeyLexer::new(source).collect::<Result<_>>().unwrap();
+
+        let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type).collect();
+        assert_eq!(types, vec![
+            TokenType::Let, TokenType::Identifier, TokenType::Assign, TokenType::Integer, TokenType::Semicolon,
+            TokenType::Identifier, TokenType::Slash, TokenType::Integer, TokenType::EndOfFile,
+        ]);
+
+        assert_eq!(tokens[0].leading_trivia(), vec![
+            Trivia { kind: TriviaKind::Comment, text: "// header" },
+            Trivia { kind: TriviaKind::Whitespace, text: "\n" },
+        ]);
+        assert_eq!(tokens[5].trivia, " // trailing\n\n  // lone\n");
+        assert_eq!(tokens[5].span.line_start, 4);
+        assert_eq!(tokens[8].trivia, "\n");
+
+        let text: String = tokens.iter()
+            .map(|token| format!("{}{}", token.trivia, &source[token.span.byte_start..token.span.byte_end]))
+            .collect();
+        assert_eq!(text, source);
+    }
+
+    #[test]
+    fn test_lexer_large_input() {
+        let source = "let value = [1, \"two\", three];\n".repeat(50_000);
+
+        let tokens = MonkeyLexer::new(&source).collect::<Result<Vec<Token>>>().unwrap();
+
+        assert_eq!(tokens.len(), 11 * 50_000 + 1);
+        assert_eq!(tokens.last().unwrap().span.line_start, 49_999);
+    }
 }
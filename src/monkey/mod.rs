@@ -9,5 +9,8 @@ pub mod token;
 
 
 pub mod interpreter;
+pub mod formatter;
+pub mod checker;
+pub mod optimizer;
 
 pub type Result<T> = core::result::Result<T, Error>;
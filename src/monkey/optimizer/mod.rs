@@ -0,0 +1,208 @@
+use std::iter;
+
+use crate::monkey::parser::ast::{ExpressionNode, Fold, Operator, Program, StatementNode};
+use crate::monkey::parser::ast::fold::fold_expression;
+use crate::monkey::token::Span;
+
+/// Fold constant expressions and drop code which can never run.
+///
+/// Evaluating the optimized program gives the same result as the original one - anything which
+/// would fail at runtime, like `1 / 0` or an overflow, is left for the interpreter to report.
+pub fn optimize(program: Program) -> Program {
+    Optimizer.fold_program(program)
+}
+
+pub struct Optimizer;
+
+impl Fold for Optimizer {
+    fn fold_program(&mut self, program: Program) -> Program {
+        let mut result = Program::default();
+
+        let count = program.statements.len();
+        let spans = program.spans.into_iter().chain(iter::repeat_with(Span::default));
+        for (idx, (statement, span)) in program.statements.into_iter().zip(spans).enumerate() {
+            match self.fold_statement(statement) {
+                StatementNode::If { condition, consequence, alternative } => match truthiness(&condition) {
+                    // Blocks share the environment of the statement, so the branch can take its place.
+                    Some(truthy) => {
+                        let branch = if truthy { Some(*consequence) } else { alternative.map(|branch| *branch) };
+                        match branch {
+                            Some(branch) if !branch.statements.is_empty() => {
+                                let spans = branch.spans.into_iter().chain(iter::repeat_with(Span::default));
+                                for (statement, span) in branch.statements.into_iter().zip(spans) {
+                                    result.push(statement, span);
+                                }
+                            }
+                            // The value of the block is still the `null` of the skipped `if`.
+                            _ if idx + 1 == count => result.push(null_if(), span),
+                            _ => {}
+                        }
+                    }
+                    None => result.push(StatementNode::If { condition, consequence, alternative }, span),
+                },
+                statement => result.push(statement, span),
+            }
+
+            if matches!(result.statements.last(), Some(StatementNode::Return(_))) {
+                break;
+            }
+        }
+
+        result
+    }
+
+    fn fold_expression(&mut self, expression: ExpressionNode) -> ExpressionNode {
+        match fold_expression(self, expression) {
+            ExpressionNode::Infix { operator, left, right } => match fold_infix(&operator, &left, &right) {
+                Some(expression) => expression,
+                None => ExpressionNode::Infix { operator, left, right },
+            },
+            ExpressionNode::Prefix { operator, expression } => match fold_prefix(&operator, &expression) {
+                Some(expression) => expression,
+                None => ExpressionNode::Prefix { operator, expression },
+            },
+            ExpressionNode::If { condition, consequence, alternative } => match truthiness(&condition) {
+                Some(truthy) => {
+                    let branch = if truthy { Some(consequence) } else { alternative };
+                    match branch {
+                        Some(mut branch) => match branch.statements.as_slice() {
+                            [StatementNode::Expression { .. }] => {
+                                let Some(StatementNode::Expression { expression }) = branch.statements.pop() else {
+                                    unreachable!()
+                                };
+                                *expression
+                            }
+                            _ => ExpressionNode::If {
+                                condition: Box::new(ExpressionNode::Bool(true)),
+                                consequence: branch,
+                                alternative: None,
+                            },
+                        },
+                        None => {
+                            let StatementNode::If { condition, consequence, alternative } = null_if() else {
+                                unreachable!()
+                            };
+                            ExpressionNode::If { condition, consequence, alternative }
+                        }
+                    }
+                }
+                None => ExpressionNode::If { condition, consequence, alternative },
+            },
+            expression => expression,
+        }
+    }
+}
+
+/// `if (false) {}` - the smallest statement evaluating to `null`.
+fn null_if() -> StatementNode {
+    StatementNode::If {
+        condition: Box::new(ExpressionNode::Bool(false)),
+        consequence: Box::default(),
+        alternative: None,
+    }
+}
+
+/// Whether a literal condition is truthy, following `Evaluate::is_truthy`.
+fn truthiness(condition: &ExpressionNode) -> Option<bool> {
+    match condition {
+        ExpressionNode::Bool(val) => Some(*val),
+        ExpressionNode::Int(val) => Some(*val != 0),
+        ExpressionNode::String(_) => Some(true),
+        _ => None,
+    }
+}
+
+/// Follows `Evaluate::eval_prefix` for literal operands.
+fn fold_prefix(operator: &Operator, expression: &ExpressionNode) -> Option<ExpressionNode> {
+    Some(match (operator, expression) {
+        (Operator::Not, ExpressionNode::Bool(val)) => ExpressionNode::Bool(!val),
+        (Operator::Not, ExpressionNode::Int(val)) => ExpressionNode::Bool(*val == 0),
+        (Operator::Not, ExpressionNode::String(_)) => ExpressionNode::Bool(false),
+        (Operator::Neg, ExpressionNode::Int(val)) => ExpressionNode::Int(val.checked_neg()?),
+        _ => return None,
+    })
+}
+
+/// Follows `Evaluate::eval_infix` for literal operands, leaving out anything producing an error.
+fn fold_infix(operator: &Operator, left: &ExpressionNode, right: &ExpressionNode) -> Option<ExpressionNode> {
+    Some(match (left, right) {
+        (ExpressionNode::Int(left), ExpressionNode::Int(right)) => match operator {
+            Operator::Add => ExpressionNode::Int(left.checked_add(*right)?),
+            Operator::Sub => ExpressionNode::Int(left.checked_sub(*right)?),
+            Operator::Mul => ExpressionNode::Int(left.checked_mul(*right)?),
+            Operator::Div => ExpressionNode::Int(left.checked_div(*right)?),
+            Operator::Greater => ExpressionNode::Bool(left > right),
+            Operator::Less => ExpressionNode::Bool(left < right),
+            Operator::Equal => ExpressionNode::Bool(left == right),
+            Operator::NotEqual => ExpressionNode::Bool(left != right),
+            _ => return None,
+        },
+        (ExpressionNode::String(left), ExpressionNode::String(right)) if *operator == Operator::Add => {
+            ExpressionNode::String(format!("{}{}", left, right))
+        }
+        (ExpressionNode::String(left), ExpressionNode::Int(right)) if *operator == Operator::Add => {
+            ExpressionNode::String(format!("{}{}", left, right))
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::monkey::formatter::Formatter;
+    use crate::monkey::interpreter::Evaluate;
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> Program {
+        let lexer = MonkeyLexer::new(source);
+        let mut parser = Parser::new(&lexer);
+        *parser.parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_optimizer() {
+        let contents = fs::read_to_string("monkey/test_optimizer.mky").unwrap();
+        let program = optimize(parse(&contents));
+
+        insta::assert_snapshot!(Formatter::new().format(&program))
+    }
+
+    #[test]
+    fn test_optimizer_keeps_errors() {
+        let source = "1 / 0; 9223372036854775807 + 1; 9223372036854775807 * 2; true + 1; -true; \"a\" == \"a\"";
+        let program = parse(source);
+
+        assert_eq!(optimize(program.clone()), program);
+    }
+
+    #[test]
+    fn test_optimizer_preserves_result() {
+        let sources = [
+            "let day = 60 * 60 * 24; day / 2",
+            "if (1 > 2) { 10 } else if (\"yes\") { 20 } else { 30 }",
+            "let f = fn(x) { if (true) { return x * (2 + 3); 1 } }; f(4)",
+            "let f = fn() { 5; if (false) { 1 } }; f()",
+            "let f = fn() { 5; if (0) { 1 } else {} }; f()",
+            "let x = if (!0) { let y = 2; y + 1 } else { 0 }; x",
+            "let s = \"a\" + \"b\" + 1; s",
+            "if (true) { return 1; } 2",
+            "!-(3 - 5) == false",
+        ];
+
+        for source in sources {
+            let program = parse(source);
+            let optimized = optimize(program.clone());
+
+            assert_eq!(
+                Evaluate::new().evaluate(&optimized),
+                Evaluate::new().evaluate(&program),
+                "{}", source
+            );
+        }
+    }
+}
@@ -2,9 +2,17 @@ use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
 use std::rc::Rc;
 
-use crate::monkey::token::Token;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::monkey::token::{Span, Token};
+
+pub use fold::Fold;
+pub use visit::Visitor;
+
+pub mod fold;
+pub mod visit;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Operator {
     Not,
     Neg,
@@ -20,16 +28,85 @@ pub enum Operator {
     Index,
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub statements: Vec<StatementNode>,
+    /// Where each statement was written, empty for programs which did not come from source.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<Span>,
+}
+
+impl Program {
+    pub fn push(&mut self, statement: StatementNode, span: Span) {
+        self.statements.push(statement);
+        self.spans.push(span);
+    }
+
+    pub fn span(&self, idx: usize) -> Option<&Span> {
+        self.spans.get(idx)
+    }
+}
+
+/// Spans are left out so programs compare equal wherever they were parsed from.
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
+}
+
+impl Debug for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Program").field("statements", &self.statements).finish()
+    }
+}
+
+/// Name along with where it was written - only the name takes part in comparisons.
+///
+/// Serialized as `[name, span]`, the span may be left out.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Identifier(pub String, #[serde(default)] pub Span);
+
+impl Identifier {
+    pub fn new(name: &str) -> Self {
+        Identifier(name.to_string(), Span::default())
+    }
 }
 
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Debug for Identifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Identifier").field(&self.0).finish()
+    }
+}
+
+/// Where a node was written, `None` for nodes which did not come from source.
+///
+/// Left out of comparisons like the spans of `Program`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Location(pub Option<Span>);
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Identifier(pub String);
+impl PartialEq for Location {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Debug for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(span) => write!(f, "{:?}", span),
+            None => write!(f, "None"),
+        }
+    }
+}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum StatementNode {
     Let(Identifier, Box<ExpressionNode>),
     Return(Box<ExpressionNode>),
@@ -43,12 +120,16 @@ pub enum StatementNode {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ExpressionNode {
     Identifier(Identifier),
     Bool(bool),
     Int(isize),
     String(String),
+    /// String literal with embedded `${}` expressions, literal chunks are kept as `String` parts.
+    InterpolatedString {
+        parts: Vec<ExpressionNode>,
+    },
     Call {
         function: Box<ExpressionNode>,
         params: Vec<Box<ExpressionNode>>,
@@ -56,6 +137,9 @@ pub enum ExpressionNode {
     Function {
         params: Vec<Identifier>,
         body: Box<Program>,
+        /// From `fn` to the closing `}`.
+        #[serde(default)]
+        span: Location,
     },
     Infix {
         operator: Operator,
@@ -76,9 +160,14 @@ pub enum ExpressionNode {
     HashLiteral {
         params: Vec<(Box<ExpressionNode>, Box<ExpressionNode>)>,
     },
+    If {
+        condition: Box<ExpressionNode>,
+        consequence: Box<Program>,
+        alternative: Option<Box<Program>>,
+    },
 }
 
 pub struct Node {
-    token: Rc<Token>,
+    token: Rc<Token<'static>>,
     program: Program,
 }
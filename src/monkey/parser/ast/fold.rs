@@ -0,0 +1,150 @@
+use crate::monkey::parser::ast::{ExpressionNode, Identifier, Program, StatementNode};
+
+/// Rebuild a `Program` node by node.
+///
+/// Every method defaults to folding the children of the node and putting it back together, an
+/// override which still wants to descend calls the matching `fold_*` function.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, statement: StatementNode) -> StatementNode {
+        fold_statement(self, statement)
+    }
+
+    fn fold_expression(&mut self, expression: ExpressionNode) -> ExpressionNode {
+        fold_expression(self, expression)
+    }
+
+    /// Called for references as well as `let` names and function parameters.
+    fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+        identifier
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        statements: program.statements.into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        spans: program.spans,
+    }
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(folder: &mut F, statement: StatementNode) -> StatementNode {
+    match statement {
+        StatementNode::Let(identifier, expression) => {
+            StatementNode::Let(folder.fold_identifier(identifier), fold_box(folder, expression))
+        }
+        StatementNode::Return(expression) => StatementNode::Return(fold_box(folder, expression)),
+        StatementNode::If { condition, consequence, alternative } => StatementNode::If {
+            condition: fold_box(folder, condition),
+            consequence: Box::new(folder.fold_program(*consequence)),
+            alternative: alternative.map(|alternative| Box::new(folder.fold_program(*alternative))),
+        },
+        StatementNode::Expression { expression } => StatementNode::Expression {
+            expression: fold_box(folder, expression),
+        },
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(folder: &mut F, expression: ExpressionNode) -> ExpressionNode {
+    match expression {
+        ExpressionNode::Identifier(identifier) => ExpressionNode::Identifier(folder.fold_identifier(identifier)),
+        ExpressionNode::Bool(_) | ExpressionNode::Int(_) | ExpressionNode::String(_) => expression,
+        ExpressionNode::InterpolatedString { parts } => ExpressionNode::InterpolatedString {
+            parts: parts.into_iter().map(|part| folder.fold_expression(part)).collect(),
+        },
+        ExpressionNode::Call { function, params } => ExpressionNode::Call {
+            function: fold_box(folder, function),
+            params: params.into_iter().map(|param| fold_box(folder, param)).collect(),
+        },
+        ExpressionNode::Function { params, body, span } => ExpressionNode::Function {
+            params: params.into_iter().map(|param| folder.fold_identifier(param)).collect(),
+            body: Box::new(folder.fold_program(*body)),
+            span,
+        },
+        ExpressionNode::Infix { operator, left, right } => ExpressionNode::Infix {
+            operator,
+            left: fold_box(folder, left),
+            right: fold_box(folder, right),
+        },
+        ExpressionNode::Prefix { operator, expression } => ExpressionNode::Prefix {
+            operator,
+            expression: fold_box(folder, expression),
+        },
+        ExpressionNode::ArrayLiteral { params } => ExpressionNode::ArrayLiteral {
+            params: params.into_iter().map(|param| fold_box(folder, param)).collect(),
+        },
+        ExpressionNode::Index { left, index } => ExpressionNode::Index {
+            left: fold_box(folder, left),
+            index: fold_box(folder, index),
+        },
+        ExpressionNode::HashLiteral { params } => ExpressionNode::HashLiteral {
+            params: params.into_iter()
+                .map(|(key, val)| (fold_box(folder, key), fold_box(folder, val)))
+                .collect(),
+        },
+        ExpressionNode::If { condition, consequence, alternative } => ExpressionNode::If {
+            condition: fold_box(folder, condition),
+            consequence: Box::new(folder.fold_program(*consequence)),
+            alternative: alternative.map(|alternative| Box::new(folder.fold_program(*alternative))),
+        },
+    }
+}
+
+/// Fold a boxed expression, reusing the allocation.
+fn fold_box<F: Fold + ?Sized>(folder: &mut F, mut expression: Box<ExpressionNode>) -> Box<ExpressionNode> {
+    *expression = folder.fold_expression(*expression);
+    expression
+}
+
+#[cfg(test)]
+mod test {
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    fn parse(source: &str) -> Program {
+        let lexer = MonkeyLexer::new(source);
+        let mut parser = Parser::new(&lexer);
+        *parser.parse_program().unwrap()
+    }
+
+    /// Prefix every name, bindings included.
+    struct Rename;
+
+    impl Fold for Rename {
+        fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+            Identifier(format!("_{}", identifier.0), identifier.1)
+        }
+    }
+
+    /// Double every integer literal.
+    struct Double;
+
+    impl Fold for Double {
+        fn fold_expression(&mut self, expression: ExpressionNode) -> ExpressionNode {
+            match expression {
+                ExpressionNode::Int(value) => ExpressionNode::Int(value * 2),
+                expression => fold_expression(self, expression),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let program = parse("let f = fn(a) { if (a > 1) { [a, 2] } else { {a: 3}[a] } }; f(4);");
+
+        assert_eq!(
+            Rename.fold_program(program.clone()),
+            parse("let _f = fn(_a) { if (_a > 1) { [_a, 2] } else { {_a: 3}[_a] } }; _f(4);"),
+        );
+        assert_eq!(
+            Double.fold_program(program),
+            parse("let f = fn(a) { if (a > 2) { [a, 4] } else { {a: 6}[a] } }; f(8);"),
+        );
+    }
+}
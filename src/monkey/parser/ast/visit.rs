@@ -0,0 +1,153 @@
+use crate::monkey::parser::ast::{ExpressionNode, Identifier, Program, StatementNode};
+
+/// Read only walk over a `Program`.
+///
+/// Every method defaults to visiting the children of the node, an override which still wants
+/// to descend calls the matching `walk_*` function.
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionNode) {
+        walk_expression(self, expression)
+    }
+
+    /// Called for references as well as `let` names and function parameters.
+    fn visit_identifier(&mut self, _identifier: &Identifier) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for statement in &program.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &StatementNode) {
+    match statement {
+        StatementNode::Let(identifier, expression) => {
+            visitor.visit_identifier(identifier);
+            visitor.visit_expression(expression);
+        }
+        StatementNode::Return(expression) => visitor.visit_expression(expression),
+        StatementNode::If { condition, consequence, alternative } => {
+            walk_if(visitor, condition, consequence, alternative)
+        }
+        StatementNode::Expression { expression } => visitor.visit_expression(expression),
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &ExpressionNode) {
+    match expression {
+        ExpressionNode::Identifier(identifier) => visitor.visit_identifier(identifier),
+        ExpressionNode::Bool(_) | ExpressionNode::Int(_) | ExpressionNode::String(_) => {}
+        ExpressionNode::InterpolatedString { parts } => {
+            for part in parts {
+                visitor.visit_expression(part);
+            }
+        }
+        ExpressionNode::Call { function, params } => {
+            visitor.visit_expression(function);
+            for param in params {
+                visitor.visit_expression(param);
+            }
+        }
+        ExpressionNode::Function { params, body, .. } => {
+            for param in params {
+                visitor.visit_identifier(param);
+            }
+            visitor.visit_program(body);
+        }
+        ExpressionNode::Infix { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        ExpressionNode::Prefix { expression, .. } => visitor.visit_expression(expression),
+        ExpressionNode::ArrayLiteral { params } => {
+            for param in params {
+                visitor.visit_expression(param);
+            }
+        }
+        ExpressionNode::Index { left, index } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(index);
+        }
+        ExpressionNode::HashLiteral { params } => {
+            for (key, val) in params {
+                visitor.visit_expression(key);
+                visitor.visit_expression(val);
+            }
+        }
+        ExpressionNode::If { condition, consequence, alternative } => {
+            walk_if(visitor, condition, consequence, alternative)
+        }
+    }
+}
+
+fn walk_if<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    condition: &ExpressionNode,
+    consequence: &Program,
+    alternative: &Option<Box<Program>>,
+) {
+    visitor.visit_expression(condition);
+    visitor.visit_program(consequence);
+    if let Some(alternative) = alternative {
+        visitor.visit_program(alternative);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    /// Names of every identifier, in visiting order.
+    #[derive(Default)]
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_identifier(&mut self, identifier: &Identifier) {
+            self.0.push(identifier.0.clone());
+        }
+    }
+
+    /// Number of function literals, not descending into the ones it finds.
+    #[derive(Default)]
+    struct Functions(usize);
+
+    impl Visitor for Functions {
+        fn visit_expression(&mut self, expression: &ExpressionNode) {
+            match expression {
+                ExpressionNode::Function { .. } => self.0 += 1,
+                _ => walk_expression(self, expression),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let source = r#"
+            let add = fn(a, b) { a + b };
+            if (add(x, 1) > 2) { [y, {"k": z}][0] } else { "${w}" }
+            let nested = fn() { fn(c) { c } };
+        "#;
+        let lexer = MonkeyLexer::new(source);
+        let mut parser = Parser::new(&lexer);
+        let program = parser.parse_program().unwrap();
+
+        let mut names = Names::default();
+        names.visit_program(&program);
+        assert_eq!(names.0, vec!["add", "a", "b", "a", "b", "add", "x", "y", "z", "w", "nested", "c", "c"]);
+
+        let mut functions = Functions::default();
+        functions.visit_program(&program);
+        assert_eq!(functions.0, 2);
+    }
+}
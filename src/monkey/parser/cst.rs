@@ -0,0 +1,317 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::fmt::{Debug, Display, Formatter};
+use std::iter::Peekable;
+use std::rc::Rc;
+
+use crate::monkey::lexer::{Lexer, MonkeyLexer};
+use crate::monkey::parser::ast::Program;
+use crate::monkey::parser::Parser;
+use crate::monkey::Result;
+use crate::monkey::token::{Span, Token, TriviaKind};
+
+/// Concrete syntax tree - every token of the source, trivia included, in source order.
+///
+/// Unlike the `ast`, nothing is dropped: printing the tree gives back the exact source. The
+/// `Parser` builds it when asked to, recording the tokens each of its rules went through.
+pub struct SyntaxTree<'a> {
+    source: &'a str,
+    pub root: SyntaxNode<'a>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SyntaxKind {
+    Program,
+    Block,
+
+    LetStatement,
+    ReturnStatement,
+    IfStatement,
+    ExpressionStatement,
+
+    /// Identifier, integer, boolean or plain string.
+    Literal,
+    InterpolatedString,
+    PrefixExpression,
+    InfixExpression,
+    ParenExpression,
+    FunctionLiteral,
+    ParameterList,
+    CallExpression,
+    ArgumentList,
+    IndexExpression,
+    ArrayLiteral,
+    HashLiteral,
+    HashEntry,
+    IfExpression,
+    ElseClause,
+}
+
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(Token<'a>),
+}
+
+pub struct SyntaxNode<'a> {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    pub fn parse(source: &'a str) -> Result<Self> {
+        let lexer = MonkeyLexer::new(source);
+        let mut parser = Parser::new(&lexer).with_syntax_tree();
+        parser.parse_program()?;
+        let root = parser.syntax_tree();
+
+        Ok(SyntaxTree { source, root })
+    }
+
+    /// Abstract syntax tree used by the interpreter, parsed from the tokens of this one.
+    pub fn to_program(&self) -> Result<Program> {
+        let lexer = TreeLexer { tokens: RefCell::new(self.root.descendant_tokens().into_iter()) };
+        Ok(*Parser::new(&lexer).parse_program()?)
+    }
+}
+
+/// Lexer handing out the tokens of a syntax tree, the last one being the end of the file.
+struct TreeLexer<'t, 'a> {
+    tokens: RefCell<std::vec::IntoIter<&'t Token<'a>>>,
+}
+
+impl<'a> Lexer<'a> for TreeLexer<'_, 'a> {
+    fn token(&self) -> Result<Token<'a>> {
+        let mut tokens = self.tokens.borrow_mut();
+        let token = match tokens.len() {
+            1 => tokens.as_slice()[0],
+            _ => tokens.next().unwrap(),
+        };
+        Ok(token.clone())
+    }
+}
+
+impl Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for token in self.root.descendant_tokens() {
+            write!(f, "{}{}", token.trivia, &self.source[token.span.byte_start..token.span.byte_end])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> SyntaxNode<'a> {
+    fn new(kind: SyntaxKind, children: Vec<SyntaxElement<'a>>) -> Self {
+        SyntaxNode { kind, children }
+    }
+
+    /// Child nodes, skipping tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Child tokens, skipping nodes.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    /// Every token below this node in source order.
+    pub fn descendant_tokens(&self) -> Vec<&Token<'a>> {
+        let mut result = Vec::new();
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => result.extend(node.descendant_tokens()),
+                SyntaxElement::Token(token) => result.push(token),
+            }
+        }
+
+        result
+    }
+
+    /// Span from the first to the last token of the node.
+    pub fn span(&self) -> Span {
+        let tokens = self.descendant_tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.to(&last.span),
+            _ => Span::default(),
+        }
+    }
+
+    fn dump(&self, depth: usize, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}{:?}", "  ".repeat(depth), self.kind)?;
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.dump(depth + 1, f)?,
+                SyntaxElement::Token(token) => {
+                    for trivia in token.leading_trivia() {
+                        if trivia.kind == TriviaKind::Comment {
+                            writeln!(f, "{}{:?}", "  ".repeat(depth + 1), trivia)?;
+                        }
+                    }
+                    writeln!(f, "{}{:?}", "  ".repeat(depth + 1), token)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.dump(0, f)
+    }
+}
+
+/// Node of the syntax tree over the tokens `start..=end` the parser went through.
+pub(super) struct NodeRange {
+    kind: SyntaxKind,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Record the tokens and nodes of the syntax tree while parsing, which parsers otherwise skip.
+    pub(super) fn with_syntax_tree(mut self) -> Self {
+        self.record = true;
+        self.tokens.push(Rc::clone(&self.token_current));
+        self
+    }
+
+    /// Index of the current token, where a node starting now begins.
+    pub(super) fn start_node(&self) -> usize {
+        self.tokens.len().saturating_sub(1)
+    }
+
+    /// Finish the node begun at `start`, up to and including the current token.
+    pub(super) fn finish_node(&mut self, start: usize, kind: SyntaxKind) {
+        if self.record {
+            let end = self.tokens.len() - 1;
+            self.nodes.push(NodeRange { kind, start, end });
+        }
+    }
+
+    /// Finish the node begun at `start`, up to the token before the current one.
+    pub(super) fn finish_node_before(&mut self, start: usize, kind: SyntaxKind) {
+        if self.record {
+            let end = self.tokens.len() - 2;
+            self.nodes.push(NodeRange { kind, start, end });
+        }
+    }
+
+    /// Tree of the nodes finished so far, rooted at the `Program` node of `parse_program`.
+    fn syntax_tree(&mut self) -> SyntaxNode<'static> {
+        // Outer nodes first - of nodes over the same tokens, the one finished last.
+        let mut nodes: Vec<_> = std::mem::take(&mut self.nodes).into_iter().enumerate().collect();
+        nodes.sort_by_key(|(order, node)| (node.start, Reverse(node.end), Reverse(*order)));
+
+        let mut nodes = nodes.into_iter().map(|(_, node)| node).peekable();
+        let mut tokens = self.tokens.iter().map(|token| token.to_static()).enumerate().peekable();
+
+        let root = nodes.next().unwrap();
+        build(root, &mut tokens, &mut nodes)
+    }
+}
+
+fn build(
+    node: NodeRange,
+    tokens: &mut Peekable<impl Iterator<Item = (usize, Token<'static>)>>,
+    nodes: &mut Peekable<impl Iterator<Item = NodeRange>>,
+) -> SyntaxNode<'static> {
+    let mut children = Vec::new();
+
+    while let Some(&(index, _)) = tokens.peek() {
+        if index > node.end {
+            break;
+        }
+
+        match nodes.next_if(|child| child.start == index) {
+            Some(child) => children.push(SyntaxElement::Node(build(child, tokens, nodes))),
+            None => children.push(SyntaxElement::Token(tokens.next().unwrap().1)),
+        }
+    }
+
+    SyntaxNode::new(node.kind, children)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::monkey::error::Error;
+    use crate::monkey::parser::ast::StatementNode;
+    use crate::monkey::token::TokenType;
+
+    use super::*;
+
+    /// Every fixture which is a valid program.
+    fn programs() -> Vec<String> {
+        let mut files: Vec<_> = fs::read_dir("monkey").unwrap()
+            .chain(fs::read_dir("example").unwrap())
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !path.to_string_lossy().contains("lexer"))
+            .collect();
+        files.sort();
+
+        files.iter().map(|file| fs::read_to_string(file).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_cst() {
+        let contents = fs::read_to_string("monkey/test_cst.mky").unwrap();
+        let tree = SyntaxTree::parse(&contents).unwrap();
+
+        insta::assert_snapshot!(format!("{:?}", tree.root))
+    }
+
+    #[test]
+    fn test_cst_lossless() {
+        let mut sources = programs();
+        sources.push("let a = 1;\r\n// windows\r\nlet b = \"\"\"\r\n  two\r\n\"\"\";  \r\n".to_string());
+        sources.push("  \n\t// only trivia".to_string());
+
+        for source in sources {
+            let tree = SyntaxTree::parse(&source).unwrap();
+            assert_eq!(tree.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_cst_to_program() {
+        for source in programs() {
+            let lexer = MonkeyLexer::new(&source);
+            let mut parser = Parser::new(&lexer);
+            let program = parser.parse_program().unwrap();
+            // Only parsers building a syntax tree record it.
+            assert!(parser.tokens.is_empty() && parser.nodes.is_empty());
+
+            let tree = SyntaxTree::parse(&source).unwrap();
+            assert_eq!(tree.to_program().unwrap(), *program, "{}", source);
+
+            // Both trees hold the same statements.
+            let kinds: Vec<_> = tree.root.nodes().map(|node| node.kind).collect();
+            let expected: Vec<_> = program.statements.iter()
+                .map(|statement| match statement {
+                    StatementNode::Let(..) => SyntaxKind::LetStatement,
+                    StatementNode::Return(_) => SyntaxKind::ReturnStatement,
+                    StatementNode::If { .. } => SyntaxKind::IfStatement,
+                    StatementNode::Expression { .. } => SyntaxKind::ExpressionStatement,
+                })
+                .collect();
+            assert_eq!(kinds, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_cst_error() {
+        let error = SyntaxTree::parse("let x = (1 + 2;").err().unwrap();
+
+        assert!(matches!(error, Error::InvalidTokenError(token) if token.token_type == TokenType::Semicolon));
+    }
+}
@@ -2,11 +2,12 @@ use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::monkey::error::Error;
-use crate::monkey::parser::ast::{ExpressionNode, Identifier, Operator, StatementNode};
+use crate::monkey::parser::ast::{ExpressionNode, Identifier, Location, Operator, StatementNode};
+use crate::monkey::parser::cst::SyntaxKind;
 use crate::monkey::parser::Parser;
 use crate::monkey::Result;
 use crate::monkey::token::{Token, TokenType};
-use crate::try_next;
+use crate::{expect, try_next};
 
 #[derive(Copy, Clone)]
 pub enum OrderOfOps {
@@ -21,6 +22,21 @@ pub enum OrderOfOps {
     Index,
 }
 
+impl OrderOfOps {
+    /// Binding power of `token_type` when it follows an expression.
+    pub fn of(token_type: TokenType) -> Self {
+        match token_type {
+            TokenType::Equal | TokenType::NotEqual => OrderOfOps::Equals,
+            TokenType::LessThan | TokenType::GreaterThan => OrderOfOps::LessGreater,
+            TokenType::Plus | TokenType::Minus => OrderOfOps::Sum,
+            TokenType::Asterisk | TokenType::Slash => OrderOfOps::Product,
+            TokenType::LeftParen => OrderOfOps::Call,
+            TokenType::LeftBracket => OrderOfOps::Index,
+            _ => OrderOfOps::Invalid,
+        }
+    }
+}
+
 impl<'a> Parser<'a> {
     pub fn parse_expression(&mut self) -> Result<StatementNode> {
         let expression = self.parse_expression_node(OrderOfOps::Lowest)?;
@@ -31,17 +47,21 @@ impl<'a> Parser<'a> {
     }
 
     fn order_of_operation(&self, token_type: TokenType) -> OrderOfOps {
-        match token_type {
-            TokenType::Equal | TokenType::NotEqual => OrderOfOps::Equals,
-            TokenType::LessThan | TokenType::GreaterThan => OrderOfOps::LessGreater,
-            TokenType::Plus | TokenType::Minus => OrderOfOps::Sum,
-            TokenType::Asterisk | TokenType::Slash => OrderOfOps::Product,
-            TokenType::LeftParen => OrderOfOps::Call,
-            TokenType::LeftBracket => OrderOfOps::Index,
-            _ => OrderOfOps::Invalid,
-        }
+        OrderOfOps::of(token_type)
     }
     pub fn parse_expression_node(&mut self, precedence: OrderOfOps) -> Result<Box<ExpressionNode>> {
+        let node = self.start_node();
+        let kind = match self.current().token_type {
+            TokenType::Bang | TokenType::Minus => SyntaxKind::PrefixExpression,
+            TokenType::LeftParen => SyntaxKind::ParenExpression,
+            TokenType::Function => SyntaxKind::FunctionLiteral,
+            TokenType::InterpolationStart => SyntaxKind::InterpolatedString,
+            TokenType::LeftBracket => SyntaxKind::ArrayLiteral,
+            TokenType::LeftBrace => SyntaxKind::HashLiteral,
+            TokenType::If => SyntaxKind::IfExpression,
+            _ => SyntaxKind::Literal,
+        };
+
         let mut left_result: Box<ExpressionNode> = match self.current().token_type {
             TokenType::Identifier => self.parse_identifier()?,
             TokenType::Integer => self.parse_integer_literal()?,
@@ -52,16 +72,25 @@ impl<'a> Parser<'a> {
             TokenType::LeftParen => self.parse_group()?,
             TokenType::Function => self.parse_fn()?,
             TokenType::String => self.parse_string()?,
+            TokenType::InterpolationStart => self.parse_interpolated_string()?,
             TokenType::LeftBracket => self.parse_array_literal()?,
             TokenType::LeftBrace => self.parse_hash_literal()?,
-            _ => return Err(Error::InvalidTokenError(Rc::clone(self.current())))
+            TokenType::If => self.parse_if_expression()?,
+            _ => return Err(self.invalid_token())
         };
+        self.finish_node(node, kind);
 
         let precedence_order = precedence as isize;
         while self.peek().token_type != TokenType::Semicolon && precedence_order < self.order_of_operation(self.peek().token_type) as isize {
             self.next();
 
+            let kind = match self.current().token_type {
+                TokenType::LeftParen => SyntaxKind::CallExpression,
+                TokenType::LeftBracket => SyntaxKind::IndexExpression,
+                _ => SyntaxKind::InfixExpression,
+            };
             let infix = self.parse_infix_node(left_result)?;
+            self.finish_node(node, kind);
 
             left_result = infix;
         }
@@ -70,7 +99,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_identifier(&self) -> Result<Box<ExpressionNode>> {
-        let result = ExpressionNode::Identifier(Identifier(self.current().literal.clone()));
+        let result = ExpressionNode::Identifier(Identifier(self.current().literal.to_string(), self.current().span.clone()));
         Ok(Box::new(result))
     }
 
@@ -86,7 +115,7 @@ impl<'a> Parser<'a> {
             TokenType::GreaterThan => self.parse_infix(left, Operator::Greater)?,
             TokenType::LeftParen => self.parse_call(left, Operator::Call)?,
             TokenType::LeftBracket => self.parse_index(left, Operator::Index)?,
-            token @ _ => return Err(Error::InvalidTokenError(Rc::clone(self.current())))
+            token @ _ => return Err(self.invalid_token())
         })
     }
 
@@ -119,6 +148,7 @@ impl<'a> Parser<'a> {
         let expression = self.parse_expression_node(OrderOfOps::Lowest)?;
 
         self.next();
+        expect!(self, TokenType::RightParen);
 
         Ok(expression)
     }
@@ -128,18 +158,17 @@ impl<'a> Parser<'a> {
         let token = Rc::clone(&self.token_current);
         self.next();
 
+        let node = self.start_node();
         try_next!(self, TokenType::LeftParen);
         let params = self.parse_fn_param()?;
-        try_next!(self, TokenType::RightParen);
-
-        try_next!(self, TokenType::LeftBrace);
-        let body = self.parse_program()?;
+        expect!(self, TokenType::RightParen);
+        self.finish_node(node, SyntaxKind::ParameterList);
+        self.next();
 
-        if self.peek().token_type == TokenType::Semicolon {
-            try_next!(self, TokenType::RightBrace);
-        }
+        let body = self.parse_block()?;
+        let span = Location(Some(token.span.to(&self.current().span)));
 
-        Ok(Box::new(ExpressionNode::Function { params, body }))
+        Ok(Box::new(ExpressionNode::Function { params, body, span }))
     }
 
     fn parse_fn_param(&mut self) -> Result<Vec<Identifier>> {
@@ -150,7 +179,8 @@ impl<'a> Parser<'a> {
         }
 
         loop {
-            result.push(Identifier(self.current().literal.clone()));
+            expect!(self, TokenType::Identifier);
+            result.push(Identifier(self.current().literal.to_string(), self.current().span.clone()));
             self.next();
 
             if self.current().token_type == TokenType::RightParen {
@@ -164,9 +194,10 @@ impl<'a> Parser<'a> {
     fn parse_call(&mut self, function: Box<ExpressionNode>, _: Operator) -> Result<Box<ExpressionNode>> {
         let token = Rc::clone(&self.token_current);
 
+        let node = self.start_node();
         try_next!(self, TokenType::LeftParen);
         let params = self.parse_expression_list(TokenType::RightParen)?;
-        // try_next!(self, TokenType::RightParen);
+        self.finish_node(node, SyntaxKind::ArgumentList);
 
         Ok(Box::new(ExpressionNode::Call { function, params }))
     }
@@ -177,6 +208,7 @@ impl<'a> Parser<'a> {
         try_next!(self, TokenType::LeftBracket);
         let index = self.parse_expression_node(OrderOfOps::Lowest)?;
         self.next();
+        expect!(self, TokenType::RightBracket);
 
         return Ok(Box::new(
             ExpressionNode::Index { left, index }
@@ -205,7 +237,7 @@ impl<'a> Parser<'a> {
 
     pub fn parse_integer_literal(&mut self) -> Result<Box<ExpressionNode>> {
         let Ok(value) = self.current().literal.parse::<isize>() else {
-            return Err(Error::InvalidTokenError(Rc::clone(self.current())));
+            return Err(self.invalid_token());
         };
 
         Ok(Box::new(ExpressionNode::Int(value)))
@@ -223,11 +255,35 @@ impl<'a> Parser<'a> {
     fn parse_string(&self) -> Result<Box<ExpressionNode>> {
         Ok(Box::new(
             ExpressionNode::String(
-                self.current().literal.clone()
+                self.current().literal.to_string()
             )
         ))
     }
 
+    fn parse_interpolated_string(&mut self) -> Result<Box<ExpressionNode>> {
+        let mut parts: Vec<ExpressionNode> = Vec::new();
+
+        loop {
+            if !self.current().literal.is_empty() {
+                parts.push(*self.parse_string()?);
+            }
+
+            if self.current().token_type == TokenType::InterpolationEnd {
+                break;
+            }
+            self.next();
+
+            parts.push(*self.parse_expression_node(OrderOfOps::Lowest)?);
+            self.next();
+
+            if !matches!(self.current().token_type, TokenType::InterpolationMiddle | TokenType::InterpolationEnd) {
+                return Err(self.invalid_token());
+            }
+        }
+
+        Ok(Box::new(ExpressionNode::InterpolatedString { parts }))
+    }
+
     fn parse_array_literal(&mut self) -> Result<Box<ExpressionNode>> {
         let token = Rc::clone(&self.token_current);
 
@@ -240,18 +296,20 @@ impl<'a> Parser<'a> {
     fn parse_hash_literal(&mut self) -> Result<Box<ExpressionNode>> {
         let mut params: Vec<(Box<ExpressionNode>, Box<ExpressionNode>)> = Vec::new();
 
+        try_next!(self, TokenType::LeftBrace);
         if self.current().token_type == TokenType::RightBrace {
             return Ok(Box::new(ExpressionNode::HashLiteral { params }));
         }
 
-        try_next!(self, TokenType::LeftBrace);
         loop {
+            let entry = self.start_node();
             let key = self.parse_expression_node(OrderOfOps::Lowest)?;
             self.next();
 
 
             try_next!(self, TokenType::Colon);
             let val = self.parse_expression_node(OrderOfOps::Lowest)?;
+            self.finish_node(entry, SyntaxKind::HashEntry);
             self.next();
 
             params.push((key, val));
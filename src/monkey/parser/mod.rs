@@ -1,62 +1,107 @@
+use std::borrow::Cow;
 use std::rc::Rc;
 
+use crate::monkey::error::Error;
 use crate::monkey::lexer::Lexer;
 use crate::monkey::parser::ast::{Program, StatementNode};
+use crate::monkey::parser::cst::{NodeRange, SyntaxKind};
 use crate::monkey::Result;
-use crate::monkey::token::{Token, TokenType};
+use crate::monkey::token::{Span, Token, TokenType};
 use crate::monkey::token::TokenType::Semicolon;
 
 pub mod parser;
 pub mod expression;
 pub mod ast;
+pub mod cst;
 
 
 #[macro_export]
 macro_rules! try_next {
     ($self: ident, $token: expr) => {
         if $self.current().token_type != $token {
-            return Err(Error::InvalidTokenError(Rc::clone($self.current())));
+            return Err($self.invalid_token());
         }
         $self.next();
     }
 }
 
-fn next_token(lexer: &dyn Lexer) -> Token {
+#[macro_export]
+macro_rules! expect {
+    ($self: ident, $token: expr) => {
+        if $self.current().token_type != $token {
+            return Err($self.invalid_token());
+        }
+    }
+}
+
+/// Next token of `lexer`, or the end of the file after recording the error the lexer failed with.
+fn next_token<'a>(lexer: &dyn Lexer<'a>, error: &mut Option<Error>, span: &Span) -> Token<'a> {
     match lexer.token() {
         Ok(token) => token,
-        Err(message) => panic!("Unable to parse: {message}")
+        Err(err) => {
+            error.get_or_insert(err);
+            Token { token_type: TokenType::EndOfFile, literal: Cow::Borrowed(""), span: span.clone(), trivia: Cow::Borrowed("") }
+        }
     }
 }
 
 pub struct Parser<'a> {
-    lexer: &'a dyn Lexer,
+    lexer: &'a dyn Lexer<'a>,
 
-    token_current: Rc<Token>,
-    token_peek: Rc<Token>,
+    token_current: Rc<Token<'a>>,
+    token_peek: Rc<Token<'a>>,
+    /// Span of the token before `token_current`, where the last statement ended.
+    span_previous: Span,
+    /// First error of the lexer, reported instead of whatever the parser made of the truncated input.
+    lexer_error: Option<Error>,
+    /// Whether `tokens` and `nodes` are recorded, only when building a syntax tree.
+    record: bool,
+    /// Every token made current so far, the last one is `token_current`.
+    tokens: Vec<Rc<Token<'a>>>,
+    /// Nodes of the concrete syntax tree in the order they were finished, see `cst`.
+    nodes: Vec<NodeRange>,
 }
 
 impl <'a> Parser <'a> {
-    pub fn new(lexer: &'a dyn Lexer) -> Self {
-        let token_current = Rc::new(next_token(lexer));
-        let token_peek = Rc::new(next_token(lexer));
+    pub fn new(lexer: &'a dyn Lexer<'a>) -> Self {
+        let mut lexer_error = None;
+        let token_current = Rc::new(next_token(lexer, &mut lexer_error, &Span::default()));
+        let token_peek = Rc::new(next_token(lexer, &mut lexer_error, &token_current.span));
         Parser {
             lexer,
+            record: false,
+            tokens: Vec::new(),
             token_current,
             token_peek,
+            span_previous: Span::default(),
+            lexer_error,
+            nodes: Vec::new(),
         }
     }
 
-    pub fn current(&self) -> &Rc<Token> {
+    pub fn current(&self) -> &Rc<Token<'a>> {
         return &self.token_current;
     }
 
-    pub fn peek(&self) -> &Rc<Token> {
+    pub fn peek(&self) -> &Rc<Token<'a>> {
         return &self.token_peek;
     }
 
+    /// Error for the current token not being what the grammar expects.
+    pub fn invalid_token(&self) -> Error {
+        Error::InvalidTokenError(Rc::new(self.current().to_static()))
+    }
+
     pub fn next(&mut self) {
+        let at_end = self.token_current.token_type == TokenType::EndOfFile;
+
+        self.span_previous = self.token_current.span.clone();
         self.token_current = Rc::clone(&self.token_peek);
-        self.token_peek = Rc::new(next_token(self.lexer));
+        self.token_peek = Rc::new(next_token(self.lexer, &mut self.lexer_error, &self.token_current.span));
+
+        if self.record && !at_end {
+            self.tokens.push(Rc::clone(&self.token_current));
+        }
     }
 
     pub fn skip_semicolon(&mut self) {
@@ -66,11 +111,35 @@ impl <'a> Parser <'a> {
     }
 
     pub fn parse_program(&mut self) -> Result<Box<Program>> {
+        let program = self.parse_statements();
+        if let Some(err) = self.lexer_error.take() {
+            return Err(err);
+        }
+        let program = program?;
+
+        expect!(self, TokenType::EndOfFile);
+        self.finish_node(0, SyntaxKind::Program);
+
+        Ok(program)
+    }
+
+    /// Statements up to the end of the file or the `}` closing the block.
+    fn parse_statements(&mut self) -> Result<Box<Program>> {
         let mut program: Box<Program> = Box::new(Program::default());
 
         while self.current().token_type != TokenType::EndOfFile && self.current().token_type != TokenType::RightBrace {
-            program.statements.push(self.parse_statement()?);
+            let node = self.start_node();
+            let start = self.current().span.clone();
+            let statement = self.parse_statement()?;
+            let kind = match statement {
+                StatementNode::Let(..) => SyntaxKind::LetStatement,
+                StatementNode::Return(_) => SyntaxKind::ReturnStatement,
+                StatementNode::If { .. } => SyntaxKind::IfStatement,
+                StatementNode::Expression { .. } => SyntaxKind::ExpressionStatement,
+            };
+            program.push(statement, start.to(&self.span_previous));
             self.skip_semicolon();
+            self.finish_node_before(node, kind);
         }
 
         Ok(program)
@@ -90,6 +159,8 @@ impl <'a> Parser <'a> {
 mod test {
     use std::fs;
     use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::ast::{ExpressionNode, Identifier, Visitor};
+    use crate::monkey::parser::ast::visit::{walk_expression, walk_program};
 
     use super::*;
 
@@ -129,4 +200,67 @@ mod test {
     evaluate!(test_parser_string, "test_parser_string.mky");
     evaluate!(test_parser_index, "test_parser_index.mky");
     evaluate!(test_parser_hash, "test_parser_hash.mky");
+    evaluate!(test_parser_interpolation, "test_parser_interpolation.mky");
+    evaluate!(test_parser_if_expression, "test_parser_if_expression.mky");
+
+    #[test]
+    fn test_parser_lexer_error() {
+        let lexer = MonkeyLexer::new("let x = 1;\nlet s = \"unterminated;");
+        let err = Parser::new(&lexer).parse_program().unwrap_err();
+
+        assert!(matches!(err, Error::UnterminatedStringError(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_parser_json() {
+        let lexer = MonkeyLexer::new("let inc = fn(x) { x + 1 };\n!inc(-1)");
+        let program = Parser::new(&lexer).parse_program().unwrap();
+        insta::assert_snapshot!(serde_json::to_string_pretty(&program).unwrap());
+
+        // Every fixture comes back as it was, spans included.
+        for file in ["test_parser_closure.mky", "test_parser_hash.mky", "test_parser_if_expression.mky", "test_parser_interpolation.mky", "test_parser_index.mky"] {
+            let contents = fs::read_to_string(format!("monkey/{}", file)).unwrap();
+            let lexer = MonkeyLexer::new(&contents);
+            let program = Parser::new(&lexer).parse_program().unwrap();
+
+            let loaded: Program = serde_json::from_str(&serde_json::to_string(&program).unwrap()).unwrap();
+            assert_eq!(loaded, *program, "{}", file);
+            assert_eq!(spans(&loaded), spans(&program), "{}", file);
+        }
+
+        // Syntax trees made by other tools need no spans.
+        let loaded: Program = serde_json::from_str(r#"{"statements": [{"Expression": {"expression": {"Identifier": ["x"]}}}]}"#).unwrap();
+        assert_eq!(loaded.statements, [StatementNode::Expression { expression: Box::new(ExpressionNode::Identifier(Identifier::new("x"))) }]);
+        assert_eq!(spans(&loaded), [Span::default()]);
+        let again: Program = serde_json::from_str(&serde_json::to_string(&loaded).unwrap()).unwrap();
+        assert_eq!(spans(&again), spans(&loaded));
+    }
+
+    /// Spans of every statement, identifier and function, nested ones included.
+    fn spans(program: &Program) -> Vec<Span> {
+        #[derive(Default)]
+        struct Spans(Vec<Span>);
+
+        impl Visitor for Spans {
+            fn visit_program(&mut self, program: &Program) {
+                self.0.extend(program.spans.iter().cloned());
+                walk_program(self, program)
+            }
+
+            fn visit_expression(&mut self, expression: &ExpressionNode) {
+                if let ExpressionNode::Function { span, .. } = expression {
+                    self.0.push(span.0.clone().unwrap_or_default());
+                }
+                walk_expression(self, expression)
+            }
+
+            fn visit_identifier(&mut self, identifier: &Identifier) {
+                self.0.push(identifier.1.clone());
+            }
+        }
+
+        let mut spans = Spans::default();
+        spans.visit_program(program);
+        spans.0
+    }
 }
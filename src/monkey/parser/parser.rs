@@ -1,19 +1,24 @@
 use std::rc::Rc;
 
-use crate::try_next;
+use crate::{expect, try_next};
 use crate::monkey::error::Error;
-use crate::monkey::parser::ast::{Identifier, StatementNode};
+use crate::monkey::parser::ast::{ExpressionNode, Identifier, Program, StatementNode};
+use crate::monkey::parser::cst::SyntaxKind;
 use crate::monkey::parser::expression::OrderOfOps;
 use crate::monkey::parser::Parser;
 use crate::monkey::Result;
 use crate::monkey::token::TokenType;
 
+/// Condition, consequence and optional alternative of an `if`.
+type IfParts = (Box<ExpressionNode>, Box<Program>, Option<Box<Program>>);
+
 impl <'a> Parser<'a> {
     pub fn parse_let(&mut self) -> Result<StatementNode> {
         let let_token = Rc::clone(&self.current());
         self.next();
 
-        let identifier = Identifier(self.current().literal.clone());
+        expect!(self, TokenType::Identifier);
+        let identifier = Identifier(self.current().literal.to_string(), self.current().span.clone());
         self.next();
 
         try_next!(self, TokenType::Assign);
@@ -30,47 +35,84 @@ impl <'a> Parser<'a> {
     }
 
     pub fn parse_if(&mut self) -> Result<StatementNode> {
-        let token = Rc::clone(&self.token_current);
+        let (condition, consequence, alternative) = self.parse_if_parts()?;
+
+        self.next();
+
+        Ok(
+            StatementNode::If {
+                condition,
+                consequence,
+                alternative,
+            }
+        )
+    }
+
+    pub fn parse_if_expression(&mut self) -> Result<Box<ExpressionNode>> {
+        let (condition, consequence, alternative) = self.parse_if_parts()?;
 
+        Ok(Box::new(
+            ExpressionNode::If {
+                condition,
+                consequence,
+                alternative,
+            }
+        ))
+    }
+
+    /// Parse `if (...) { ... }` with any number of `else if` and an optional `else`, leaving
+    /// the parser at the last `}`.
+    ///
+    /// An `else if` is kept as an alternative block holding a single nested `If` statement.
+    fn parse_if_parts(&mut self) -> Result<IfParts> {
         self.next();
 
         try_next!(self, TokenType::LeftParen);
 
-        let expression = self.parse_expression_node(OrderOfOps::Lowest)?;
+        let condition = self.parse_expression_node(OrderOfOps::Lowest)?;
 
         self.next();
 
         try_next!(self, TokenType::RightParen);
 
-        try_next!(self, TokenType::LeftBrace);
-
-        let consequence = self.parse_program()?;
+        let consequence = self.parse_block()?;
 
-        try_next!(self, TokenType::RightBrace);
+        if self.peek().token_type != TokenType::Else {
+            return Ok((condition, consequence, None));
+        }
 
-        let alternative = if self.current().token_type == TokenType::Else {
-            self.next();
+        self.next();
+        let clause = self.start_node();
+        self.next();
 
-            try_next!(self, TokenType::LeftBrace);
+        let alternative = if self.current().token_type == TokenType::If {
+            let node = self.start_node();
+            let start = self.current().span.clone();
+            let (condition, consequence, alternative) = self.parse_if_parts()?;
+            self.finish_node(node, SyntaxKind::IfExpression);
 
-            match self.parse_program() {
-                Ok(result) => {
-                    self.next();
-                    Some(result)
-                }
-                Err(err) => return Err(err),
-            }
+            let mut program = Program::default();
+            program.push(StatementNode::If { condition, consequence, alternative }, start.to(&self.current().span));
+            Box::new(program)
         } else {
-            None
+            self.parse_block()?
         };
+        self.finish_node(clause, SyntaxKind::ElseClause);
 
-        Ok(
-            StatementNode::If {
-                condition: expression,
-                consequence,
-                alternative,
-            }
-        )
+        Ok((condition, consequence, Some(alternative)))
+    }
+
+    /// Parse `{ ... }`, leaving the parser at the `}`.
+    pub fn parse_block(&mut self) -> Result<Box<Program>> {
+        let node = self.start_node();
+        try_next!(self, TokenType::LeftBrace);
+
+        let program = self.parse_statements()?;
+
+        expect!(self, TokenType::RightBrace);
+        self.finish_node(node, SyntaxKind::Block);
+
+        Ok(program)
     }
 
     pub fn parse_return(&mut self) -> Result<StatementNode> {
@@ -1,5 +1,8 @@
+use std::borrow::Cow;
 use std::fmt::{Debug, Formatter};
 
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TokenType {
     Illegal,
@@ -8,6 +11,9 @@ pub enum TokenType {
     Comment,
 
     String,
+    InterpolationStart,
+    InterpolationMiddle,
+    InterpolationEnd,
 
     // Identifier/Literals
     Identifier,
@@ -51,32 +57,106 @@ pub enum TokenType {
     Return,
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
     pub line_start: usize,
     pub line_end: usize,
+
+    /// Byte range within the source, `byte_end` is exclusive.
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+impl Span {
+    /// Span from the start of `self` up to the end of `end`.
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            start: self.start,
+            end: end.end,
+            line_start: self.line_start,
+            line_end: end.line_end,
+            byte_start: self.byte_start,
+            byte_end: end.byte_end,
+        }
+    }
 }
 
 impl Debug for Span {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f, "[L{}-{:?}:{:?}]",
-            self.line_start, self.start, self.end
-        )
+        if self.line_start == self.line_end {
+            write!(
+                f, "[L{}-{:?}:{:?}]",
+                self.line_start, self.start, self.end
+            )
+        } else {
+            write!(
+                f, "[L{}-{:?}:L{}-{:?}]",
+                self.line_start, self.start, self.line_end, self.end
+            )
+        }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// `//` up to, but not including, the end of the line.
+    Comment,
+}
+
+/// Piece of source text between two tokens which the parser ignores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+}
+
 #[derive(PartialEq, Clone)]
-pub struct Token {
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub literal: String,
+    /// Borrowed from the source unless the lexer had to rewrite it (string escapes).
+    pub literal: Cow<'a, str>,
 
     pub span: Span,
+
+    /// Whitespace and comments preceding the token, exactly as written.
+    pub trivia: Cow<'a, str>,
+}
+
+impl Token<'_> {
+    /// Copy of the token which no longer borrows from the source.
+    pub fn to_static(&self) -> Token<'static> {
+        Token {
+            token_type: self.token_type,
+            literal: Cow::Owned(self.literal.to_string()),
+            span: self.span.clone(),
+            trivia: Cow::Owned(self.trivia.to_string()),
+        }
+    }
+
+    /// Split the preceding trivia into whitespace runs and comments.
+    pub fn leading_trivia(&self) -> Vec<Trivia<'_>> {
+        let mut result = Vec::new();
+
+        let mut rest: &str = &self.trivia;
+        while !rest.is_empty() {
+            let (kind, length) = if rest.starts_with("//") {
+                (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+            } else {
+                (TriviaKind::Whitespace, rest.find("//").unwrap_or(rest.len()))
+            };
+
+            result.push(Trivia { kind, text: &rest[..length] });
+            rest = &rest[length..];
+        }
+
+        result
+    }
 }
 
-impl Debug for Token {
+impl Debug for Token<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f, "Token({:?}, {:?} {:?})",
@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::monkey::interpreter::hook::Hook;
+use crate::monkey::interpreter::object::Object;
+
+/// Name the script itself is profiled under.
+const MAIN: &str = "<main>";
+
+/// Totals of one function or builtin.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub calls: u64,
+    /// Time from entering to leaving, counting recursive calls once.
+    pub inclusive: Duration,
+    /// Time spent in the function itself, leaving out the functions it called.
+    pub exclusive: Duration,
+    pub allocations: u64,
+    pub bytes: usize,
+}
+
+/// A call that has not returned yet.
+struct Active {
+    name: String,
+    start: Instant,
+    /// Time spent in the calls made from this one.
+    children: Duration,
+}
+
+/// Hook recording per function call counts, times and allocations.
+pub struct Profiler {
+    stack: RefCell<Vec<Active>>,
+    stats: RefCell<HashMap<String, Stats>>,
+    /// Exclusive time of every distinct stack, its names joined with `;`.
+    folded: RefCell<HashMap<String, Duration>>,
+}
+
+impl Profiler {
+    /// Start profiling, counting the time from now as the time of the script itself.
+    pub fn new() -> Self {
+        let main = Active { name: MAIN.to_string(), start: Instant::now(), children: Duration::ZERO };
+
+        let profiler = Profiler {
+            stack: RefCell::new(vec![]),
+            stats: RefCell::new(HashMap::new()),
+            folded: RefCell::new(HashMap::new()),
+        };
+        profiler.push(main);
+        profiler
+    }
+
+    fn push(&self, active: Active) {
+        self.stats.borrow_mut().entry(active.name.clone()).or_default().calls += 1;
+        self.stack.borrow_mut().push(active);
+    }
+
+    /// Close the innermost call, charging its time.
+    fn pop(&self) {
+        let mut stack = self.stack.borrow_mut();
+        let Some(active) = stack.pop() else {
+            return;
+        };
+
+        let inclusive = active.start.elapsed();
+        let exclusive = inclusive.saturating_sub(active.children);
+        if let Some(caller) = stack.last_mut() {
+            caller.children += inclusive;
+        }
+
+        let path: Vec<&str> = stack.iter().map(|active| active.name.as_str()).chain([active.name.as_str()]).collect();
+        *self.folded.borrow_mut().entry(path.join(";")).or_default() += exclusive;
+
+        let mut stats = self.stats.borrow_mut();
+        let stats = stats.entry(active.name.clone()).or_default();
+        stats.exclusive += exclusive;
+        if !stack.iter().any(|outer| outer.name == active.name) {
+            stats.inclusive += inclusive;
+        }
+    }
+
+    /// Stop profiling and return the totals, the most exclusive time first.
+    pub fn finish(&self) -> Vec<(String, Stats)> {
+        while !self.stack.borrow().is_empty() {
+            self.pop();
+        }
+
+        let mut stats: Vec<_> = self.stats.borrow().iter().map(|(name, stats)| (name.clone(), stats.clone())).collect();
+        stats.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then_with(|| a.0.cmp(&b.0)));
+        stats
+    }
+
+    /// Stacks in the folded format flame graph tools read - `main;outer;inner microseconds`.
+    ///
+    /// Call `finish` first so the open calls are counted.
+    pub fn folded(&self) -> String {
+        let folded = self.folded.borrow();
+        let mut stacks: Vec<_> = folded.iter().collect();
+        stacks.sort();
+
+        stacks.into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+/// Table of `stats` as returned by `Profiler::finish`, times in milliseconds.
+pub fn table(stats: &[(String, Stats)]) -> String {
+    let width = stats.iter().map(|(name, _)| name.len()).chain(["function".len()]).max().unwrap_or(0);
+
+    let mut table = format!("{:<width$}  {:>8}  {:>12}  {:>12}  {:>8}  {:>10}\n", "function", "calls", "inclusive", "exclusive", "allocs", "bytes");
+    for (name, stats) in stats {
+        table += &format!(
+            "{:<width$}  {:>8}  {:>12.3}  {:>12.3}  {:>8}  {:>10}\n",
+            name,
+            stats.calls,
+            stats.inclusive.as_secs_f64() * 1000.0,
+            stats.exclusive.as_secs_f64() * 1000.0,
+            stats.allocations,
+            stats.bytes,
+        );
+    }
+    table
+}
+
+impl Hook for Profiler {
+    fn enter_call(&self, function: &Object) {
+        let name = match function {
+            Object::Function { name, .. } | Object::BuiltIn { name, .. } => name.clone(),
+            _ => "<unknown>".to_string(),
+        };
+
+        self.push(Active { name, start: Instant::now(), children: Duration::ZERO });
+    }
+
+    fn exit_call(&self) {
+        // The script itself stays open until `finish`.
+        if self.stack.borrow().len() > 1 {
+            self.pop();
+        }
+    }
+
+    fn allocate(&self, bytes: usize) {
+        let stack = self.stack.borrow();
+        let Some(active) = stack.last() else {
+            return;
+        };
+
+        let mut stats = self.stats.borrow_mut();
+        let stats = stats.entry(active.name.clone()).or_default();
+        stats.allocations += 1;
+        stats.bytes += bytes;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::monkey::interpreter::Evaluate;
+    use crate::monkey::lexer::MonkeyLexer;
+    use crate::monkey::parser::Parser;
+
+    use super::*;
+
+    fn profile(source: &str) -> Rc<Profiler> {
+        let lexer = MonkeyLexer::new(source);
+        let program = Parser::new(&lexer).parse_program().unwrap();
+
+        let profiler = Rc::new(Profiler::new());
+        Evaluate::new().with_hook(profiler.clone()).evaluate(&program).unwrap();
+        profiler
+    }
+
+    /// Everything but the times, which vary from run to run.
+    fn counts(profiler: &Profiler) -> String {
+        let mut stats = profiler.finish();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut output = String::new();
+        for (name, stats) in stats {
+            output += &format!("{}: {} calls, {} allocations, {} bytes\n", name, stats.calls, stats.allocations, stats.bytes);
+        }
+        output += "\n";
+        for line in profiler.folded().lines() {
+            output += &format!("{}\n", line.rsplit_once(' ').unwrap().0);
+        }
+        output
+    }
+
+    #[test]
+    fn test_profiler() {
+        let profiler = profile(r#"
+let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+let names = fn(arr, n) { if (n == 0) { arr } else { names(push(arr, "x" + n), n - 1) } };
+let apply = fn(f, x) { f(x) };
+fib(10);
+len(names([], 3));
+apply(fn(x) { x * 2 }, 21)
+"#);
+
+        insta::assert_snapshot!(counts(&profiler));
+    }
+
+    #[test]
+    fn test_profiler_times() {
+        let profiler = profile("let spin = fn(n) { if (n == 0) { 0 } else { spin(n - 1) + 0 } }; spin(200)");
+        let stats: HashMap<String, Stats> = profiler.finish().into_iter().collect();
+
+        // Recursive calls count once towards the inclusive time.
+        let spin = &stats["spin"];
+        assert!(spin.exclusive <= spin.inclusive);
+        assert!(spin.inclusive <= stats[MAIN].inclusive);
+        assert_eq!(stats[MAIN].inclusive, stats[MAIN].exclusive + spin.inclusive);
+
+        let table = table(&profiler.finish());
+        assert!(table.starts_with("function"));
+        assert_eq!(table.lines().count(), 3);
+    }
+}
@@ -22,12 +22,19 @@ impl Repl {
                 let lexer = MonkeyLexer::new(&command);
                 let mut parser = Parser::new(&lexer);
 
-                let program = parser.parse_program().unwrap();
+                let program = match parser.parse_program() {
+                    Ok(program) => program,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        continue;
+                    }
+                };
 
                 let mut eval = Evaluate::new();
-                let out = eval.evaluate(&program);
-
-                println!("{:?}", out);
+                match eval.evaluate(&program) {
+                    Ok(out) => println!("{}", out),
+                    Err(err) => eprintln!("{}", err),
+                }
             }
         }
     }
@@ -0,0 +1,35 @@
+use std::io::{BufRead, Write};
+
+use serde_json::Value;
+
+/// Read the next `Content-Length` framed JSON message, `None` once `input` is closed.
+///
+/// The Debug Adapter and Language Server protocols share this framing.
+pub fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) {
+    let body = message.to_string();
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
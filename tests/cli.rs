@@ -0,0 +1,156 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Exit code, stdout and stderr of `an-monkey-lang` run with `args` and `stdin`.
+fn monkey(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_an-monkey-lang"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (output.status.code().unwrap(), stdout, stderr)
+}
+
+#[test]
+fn test_cli_run() {
+    assert_eq!(monkey(&["run", "monkey/test_debugger.mky"], ""), (0, "15\n".to_string(), String::new()));
+    assert_eq!(monkey(&["run", "-"], "let a = 2; a * 21"), (0, "42\n".to_string(), String::new()));
+    assert_eq!(monkey(&["eval", "-e", "len(\"abc\")"], ""), (0, "3\n".to_string(), String::new()));
+
+    let (code, _, stderr) = monkey(&["run", "monkey/missing.mky"], "");
+    assert_eq!(code, 2);
+    assert!(stderr.starts_with("monkey/missing.mky: "), "{}", stderr);
+
+    let (code, _, stderr) = monkey(&["run", "-"], "let x = \"unterminated");
+    assert_eq!(code, 2);
+    assert!(stderr.starts_with("<stdin>: Unterminated string"), "{}", stderr);
+
+    let (code, _, stderr) = monkey(&["eval", "-e", "let f = fn() { 1 + f() }; f()", "--max-depth", "5"], "");
+    assert_eq!(code, 1);
+    assert!(stderr.starts_with("<expr>: Stack overflow"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_stages() {
+    let (code, stdout, _) = monkey(&["lex", "-"], "x + 1");
+    assert_eq!(code, 0);
+    assert_eq!(stdout.lines().nth(1), Some(r#"^ Token(Identifier, "x" [L0-0:0])"#));
+
+    let (code, stdout, _) = monkey(&["parse", "-"], "let x = 1;\nx");
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "Let(Identifier(\"x\"), Int(1))\nExpression { expression: Identifier(Identifier(\"x\")) }\n");
+
+    let (code, stdout, stderr) = monkey(&["parse", "-"], "let = 1;");
+    assert_eq!((code, stdout.as_str()), (2, ""));
+    assert!(stderr.starts_with("<stdin>: Invalid token"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_script_environment() {
+    let (code, stdout, _) = monkey(&["run", "-", "--", "a", "b"], "args");
+    assert_eq!((code, stdout.as_str()), (0, "[ \"a\", \"b\" ]\n"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_an-monkey-lang"))
+        .args(["eval", "-e", "getenv(\"MONKEY_TEST\") + env()[\"MONKEY_TEST\"]"])
+        .env("MONKEY_TEST", "banana")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\"bananabanana\"\n");
+    assert_eq!(monkey(&["eval", "-e", "getenv(\"MONKEY_UNSET\")"], "").1, "Null\n");
+
+    assert_eq!(monkey(&["eval", "-e", "exit(3); 1"], ""), (3, String::new(), String::new()));
+    assert_eq!(monkey(&["eval", "-e", "!exit(3)"], ""), (3, String::new(), String::new()));
+
+    let (code, _, stderr) = monkey(&["eval", "-e", "exit(4294967296)"], "");
+    assert_eq!(code, 1);
+    assert!(stderr.starts_with("<expr>: exit(): Invalid exit code: 4294967296"), "{}", stderr);
+
+    // An error nothing handled fails the script, wherever it happens.
+    let (code, stdout, stderr) = monkey(&["eval", "-e", "missing; 1"], "");
+    assert_eq!((code, stdout.as_str()), (1, ""));
+    assert!(stderr.starts_with("<expr>: identifier not found"), "{}", stderr);
+
+    let (code, stdout, stderr) = monkey(&["eval", "-e", "missing"], "");
+    assert_eq!((code, stdout.as_str()), (1, ""));
+    assert!(stderr.starts_with("<expr>: identifier not found"), "{}", stderr);
+
+    for source in ["let g = fn() { 1 / 0; 5 }; g() + 1", "if (missing) { 1 } else { 2 }", "[missing, 1]; 2"] {
+        let (code, stdout, _) = monkey(&["eval", "-e", source], "");
+        assert_eq!((code, stdout.as_str()), (1, ""), "{}", source);
+    }
+}
+
+#[test]
+fn test_cli_allow_fs() {
+    let (code, _, stderr) = monkey(&["eval", "-e", "exists(\"Cargo.toml\")"], "");
+    assert_eq!(code, 1);
+    assert!(stderr.starts_with("<expr>: Permission denied"), "{}", stderr);
+
+    assert_eq!(monkey(&["eval", "--allow-fs=monkey", "-e", "exists(\"monkey/test_debugger.mky\")"], "").1, "true\n");
+    assert_eq!(monkey(&["eval", "--allow-fs=monkey", "-e", "exists(\"Cargo.toml\")"], "").0, 1);
+}
+
+#[test]
+fn test_cli_lines() {
+    let (code, stdout, _) = monkey(&["-n", "-e", "if (nr != 2) { \"${nr}: ${line}\" }"], "a\nb\nc\n");
+    assert_eq!((code, stdout.as_str()), (0, "1: a\n3: c\n"));
+
+    let args = ["-n", "--begin", "let total = 0", "-e", "let total = total + json_parse(line)", "--end", "total"];
+    assert_eq!(monkey(&args, "3\n4\n"), (0, "7\n".to_string(), String::new()));
+
+    assert_eq!(monkey(&["-n", "-e", "if (nr == 2) { exit(7) }; line"], "3\n4\n5\n"), (7, "3\n".to_string(), String::new()));
+
+    let (code, stdout, stderr) = monkey(&["-n", "-e", "missing; line"], "a\n");
+    assert_eq!((code, stdout.as_str()), (1, ""));
+    assert!(stderr.starts_with("<stdin>:1: identifier not found"), "{}", stderr);
+
+    let (code, _, stderr) = monkey(&["-n", "-e", "line", "--begin", "let = 1"], "");
+    assert_eq!(code, 2);
+    assert!(stderr.starts_with("<begin>: Invalid token"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_stdin_builtins() {
+    let (_, stdout, _) = monkey(&["eval", "-e", "[read_line(), read_lines(), read_line()]"], "a\nb\r\nc\n");
+    assert_eq!(stdout, "[ \"a\", [ \"b\", \"c\" ], Null ]\n");
+
+    assert_eq!(monkey(&["eval", "-e", "len(input())"], "ab\ncd").1, "5\n");
+}
+
+#[test]
+fn test_cli_json_ast() {
+    let (code, ast, _) = monkey(&["parse", "--format", "json", "monkey/test_debugger.mky"], "");
+    assert_eq!(code, 0);
+    assert!(ast.starts_with("{\n  \"statements\": ["), "{}", ast);
+    assert_eq!(monkey(&["run", "--ast", "-"], &ast), (0, "15\n".to_string(), String::new()));
+
+    let ast = r#"{ "statements": [{ "Expression": { "expression": { "Infix": { "operator": "Mul", "left": { "Int": 6 }, "right": { "Int": 7 } } } } }] }"#;
+    assert_eq!(monkey(&["run", "--ast", "-"], ast).1, "42\n");
+
+    let (code, _, stderr) = monkey(&["run", "--ast", "-"], "let x = 1;");
+    assert_eq!(code, 2);
+    assert!(stderr.starts_with("<stdin>: Invalid syntax tree"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_fmt_comments() {
+    let dir = std::env::temp_dir().join(format!("monkey-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (kept, refused) = (dir.join("kept.mky"), dir.join("refused.mky"));
+    std::fs::write(&kept, "// one\nlet x = 1;   // two\n").unwrap();
+    std::fs::write(&refused, "let a = [1, // one\n  2];\n").unwrap();
+
+    let (code, stdout, stderr) = monkey(&["fmt", refused.to_str().unwrap(), kept.to_str().unwrap()], "");
+    assert_eq!((code, stdout.as_str()), (2, "// one\nlet x = 1; // two\n"));
+    assert!(stderr.contains("refused.mky: Generic comments within expressions can not be formatted"), "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
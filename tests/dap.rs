@@ -0,0 +1,150 @@
+use serde_json::{json, Value};
+
+use common::Connection;
+
+mod common;
+
+/// Scripted client talking to `an-monkey-lang dap`.
+struct Client {
+    connection: Connection,
+    seq: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        Client { connection: Connection::start("dap"), seq: 0 }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) -> i64 {
+        self.seq += 1;
+        self.connection.write(&json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }));
+        self.seq
+    }
+
+    fn read(&mut self) -> Value {
+        self.connection.read()
+    }
+
+    /// Send a request and return the body of its successful response.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let seq = self.send(command, arguments);
+        loop {
+            let message = self.read();
+            if message["type"] == "response" && message["request_seq"] == seq {
+                assert_eq!(message["success"], true, "{} failed: {}", command, message);
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Skip messages until the event named `event`, returning its body.
+    fn event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.read();
+            if message["type"] == "event" && message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Name and line of each frame of the paused program, innermost first.
+    fn stack(&mut self) -> Vec<(String, i64)> {
+        let body = self.request("stackTrace", json!({ "threadId": 1 }));
+        body["stackFrames"].as_array().unwrap().iter()
+            .map(|frame| (frame["name"].as_str().unwrap().to_string(), frame["line"].as_i64().unwrap()))
+            .collect()
+    }
+
+    fn locals(&mut self) -> Vec<String> {
+        let scopes = self.request("scopes", json!({ "frameId": 0 }));
+        let reference = scopes["scopes"][0]["variablesReference"].clone();
+
+        let body = self.request("variables", json!({ "variablesReference": reference }));
+        body["variables"].as_array().unwrap().iter()
+            .map(|var| format!("{} = {}", var["name"].as_str().unwrap(), var["value"].as_str().unwrap()))
+            .collect()
+    }
+}
+
+fn frames(frames: &[(&str, i64)]) -> Vec<(String, i64)> {
+    frames.iter().map(|(name, line)| (name.to_string(), *line)).collect()
+}
+
+#[test]
+fn test_dap_breakpoints() {
+    let mut client = Client::start();
+
+    let capabilities = client.request("initialize", json!({ "adapterID": "monkey" }));
+    assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+    client.event("initialized");
+
+    client.request("launch", json!({ "program": "monkey/test_debugger.mky" }));
+    let breakpoints = client.request("setBreakpoints", json!({
+        "source": { "path": "monkey/test_debugger.mky" },
+        "breakpoints": [{ "line": 3 }],
+    }));
+    assert_eq!(breakpoints["breakpoints"], json!([{ "verified": true, "line": 3 }]));
+    client.request("configurationDone", json!({}));
+
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    let threads = client.request("threads", json!({}));
+    assert_eq!(threads["threads"], json!([{ "id": 1, "name": "main" }]));
+    assert_eq!(client.stack(), frames(&[("square", 3), ("sum", 7), ("<main>", 9)]));
+    assert_eq!(client.locals(), ["a = 3", "args = [  ]", "b = 9", "n = 3", "square = fn(a)", "sum = fn(n)"]);
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    assert_eq!(client.stack(), frames(&[("sum", 7), ("sum", 7), ("<main>", 9)]));
+
+    client.request("stepIn", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(client.stack()[0], ("sum".to_string(), 7));
+
+    client.request("setBreakpoints", json!({ "source": { "path": "monkey/test_debugger.mky" }, "breakpoints": [] }));
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("output")["output"], "15\n");
+    assert_eq!(client.event("exited")["exitCode"], 0);
+    client.event("terminated");
+
+    client.request("disconnect", json!({}));
+    client.connection.finish();
+}
+
+#[test]
+fn test_dap_stop_on_entry() {
+    let mut client = Client::start();
+
+    client.request("initialize", json!({}));
+    client.request("launch", json!({ "program": "monkey/test_debugger.mky", "stopOnEntry": true }));
+    client.request("configurationDone", json!({}));
+
+    assert_eq!(client.event("stopped")["reason"], "entry");
+    assert_eq!(client.stack(), frames(&[("<main>", 1)]));
+    assert_eq!(client.locals(), ["args = [  ]"]);
+
+    client.request("next", json!({ "threadId": 1 }));
+    client.event("stopped");
+    assert_eq!(client.stack(), frames(&[("<main>", 6)]));
+    assert_eq!(client.locals(), ["args = [  ]", "square = fn(a)"]);
+
+    // Leaving while paused ends the program without running the rest.
+    client.request("disconnect", json!({}));
+    client.connection.finish();
+}
+
+#[test]
+fn test_dap_launch_missing_file() {
+    let mut client = Client::start();
+
+    client.request("initialize", json!({}));
+    let seq = client.send("launch", json!({ "program": "monkey/missing.mky" }));
+    let response = loop {
+        let message = client.read();
+        if message["request_seq"] == seq {
+            break message;
+        }
+    };
+    assert_eq!(response["success"], false);
+
+    client.connection.finish();
+}
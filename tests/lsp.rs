@@ -0,0 +1,188 @@
+use serde_json::{json, Value};
+
+use common::Connection;
+
+mod common;
+
+const URI: &str = "file:///test.mky";
+
+const SOURCE: &str = "\
+let add = fn(a, b) {
+    a + b
+};
+let total = add(1, len(\"ab\"));
+missing + total
+";
+
+/// Scripted client talking to `an-monkey-lang lsp`.
+struct Client {
+    connection: Connection,
+    id: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut client = Client { connection: Connection::start("lsp"), id: 0 };
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.connection.write(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Send a request and return its response.
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        self.id += 1;
+        self.connection.write(&json!({ "jsonrpc": "2.0", "id": self.id, "method": method, "params": params }));
+
+        loop {
+            let message = self.connection.read();
+            if message["id"] == self.id {
+                return message;
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let response = self.call(method, params);
+        assert!(response.get("error").is_none(), "{} failed: {}", method, response);
+        response["result"].clone()
+    }
+
+    /// Skip messages until the next diagnostics, returning them as `line:character message`.
+    fn diagnostics(&mut self) -> Vec<String> {
+        loop {
+            let message = self.connection.read();
+            if message["method"] == "textDocument/publishDiagnostics" {
+                return message["params"]["diagnostics"].as_array().unwrap().iter()
+                    .map(|diagnostic| {
+                        let start = &diagnostic["range"]["start"];
+                        format!("{}:{} {}", start["line"], start["character"], diagnostic["message"].as_str().unwrap())
+                    })
+                    .collect();
+            }
+        }
+    }
+
+    fn open(&mut self, text: &str) -> Vec<String> {
+        self.notify("textDocument/didOpen", json!({
+            "textDocument": { "uri": URI, "languageId": "monkey", "version": 1, "text": text },
+        }));
+        self.diagnostics()
+    }
+
+    fn at(&mut self, method: &str, line: usize, character: usize) -> Value {
+        self.request(method, json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        }))
+    }
+
+    fn finish(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        self.connection.finish();
+    }
+}
+
+#[test]
+fn test_lsp_diagnostics() {
+    let mut client = Client::start();
+
+    assert_eq!(client.open(SOURCE), ["4:0 undefined identifier `missing`"]);
+
+    client.notify("textDocument/didChange", json!({
+        "textDocument": { "uri": URI, "version": 2 },
+        "contentChanges": [{ "text": "let x = ;" }],
+    }));
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].starts_with("0:8 "), "{:?}", diagnostics);
+
+    client.notify("textDocument/didClose", json!({ "textDocument": { "uri": URI } }));
+    assert!(client.diagnostics().is_empty());
+
+    client.finish();
+}
+
+#[test]
+fn test_lsp_navigation() {
+    let mut client = Client::start();
+    client.open(SOURCE);
+
+    let hover = client.at("textDocument/hover", 3, 12);
+    assert_eq!(hover["contents"]["value"], "```monkey\nlet add = fn(a, b)\n```");
+    let hover = client.at("textDocument/hover", 3, 20);
+    assert_eq!(hover["contents"]["value"], "```monkey\nbuiltin len(_)\n```");
+    let hover = client.at("textDocument/hover", 1, 8);
+    assert_eq!(hover["contents"]["value"], "```monkey\nparameter b\n```");
+    assert_eq!(client.at("textDocument/hover", 4, 8), Value::Null);
+
+    let definition = client.at("textDocument/definition", 1, 4);
+    assert_eq!(definition["range"], json!({ "start": { "line": 0, "character": 13 }, "end": { "line": 0, "character": 14 } }));
+    let definition = client.at("textDocument/definition", 4, 11);
+    assert_eq!(definition["range"]["start"], json!({ "line": 3, "character": 4 }));
+    assert_eq!(client.at("textDocument/definition", 3, 20), Value::Null);
+
+    let completion = client.at("textDocument/completion", 4, 0);
+    let labels: Vec<&str> = completion.as_array().unwrap().iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for label in ["add", "total", "a", "len", "push", "let", "fn"] {
+        assert!(labels.contains(&label), "{} missing from {:?}", label, labels);
+    }
+
+    let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+    let symbols: Vec<(&str, i64)> = symbols.as_array().unwrap().iter()
+        .map(|symbol| (symbol["name"].as_str().unwrap(), symbol["kind"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(symbols, [("add", 12), ("total", 13)]);
+
+    client.finish();
+}
+
+#[test]
+fn test_lsp_utf16_columns() {
+    let mut client = Client::start();
+
+    // The emoji takes two UTF-16 code units, which is how the protocol counts columns.
+    assert_eq!(client.open("let s = \"😀\"; let t = s; missing + t"), ["0:25 undefined identifier `missing`"]);
+
+    let definition = client.at("textDocument/definition", 0, 35);
+    assert_eq!(definition["range"], json!({ "start": { "line": 0, "character": 18 }, "end": { "line": 0, "character": 19 } }));
+    let hover = client.at("textDocument/hover", 0, 22);
+    assert_eq!(hover["contents"]["value"], "```monkey\nlet s\n```");
+
+    client.open("json_stringify([\"😀\"])");
+    let hover = client.at("textDocument/hover", 0, 3);
+    assert_eq!(hover["contents"]["value"], "```monkey\nbuiltin json_stringify(_, _?)\n```");
+
+    client.finish();
+}
+
+#[test]
+fn test_lsp_formatting() {
+    let mut client = Client::start();
+    client.open("let  x=[1,2];\nx");
+
+    let edits = client.request("textDocument/formatting", json!({
+        "textDocument": { "uri": URI },
+        "options": { "tabSize": 4, "insertSpaces": true },
+    }));
+    assert_eq!(edits[0]["newText"], "let x = [1, 2];\nx;\n");
+
+    client.open("// a comment\nlet  x = 1;");
+    let edits = client.request("textDocument/formatting", json!({ "textDocument": { "uri": URI }, "options": {} }));
+    assert_eq!(edits[0]["newText"], "// a comment\nlet x = 1;\n");
+
+    client.open("let x = [1, // a comment\n  2];");
+    let response = client.call("textDocument/formatting", json!({ "textDocument": { "uri": URI }, "options": {} }));
+    assert!(response["error"]["message"].as_str().unwrap().contains("comments"));
+
+    let response = client.call("workspace/symbol", json!({ "query": "" }));
+    assert_eq!(response["error"]["code"], -32601);
+
+    client.finish();
+}